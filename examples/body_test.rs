@@ -1,6 +1,5 @@
 extern crate iron;
 extern crate iron_test;
-extern crate mime;
 extern crate urlencoded;
 
 use iron::{Handler, status};
@@ -27,25 +26,18 @@ fn main() {
 
 #[cfg(test)]
 mod test {
-    use iron::Headers;
-    use iron::headers::ContentType;
-    use iron::prelude::*;
-
-    use iron_test::{request, response};
-
-    use mime::Mime;
+    use iron_test::{response, Form, RequestBuilder};
 
     use super::BodyHandler;
 
     #[test]
     fn test_body() {
-        let mut headers = Headers::new();
-        let mime: Mime = "application/x-www-form-urlencoded".parse().unwrap();
-        headers.set(ContentType(mime));
-        let response = request::post("http://localhost:3000/users",
-                                     headers,
-                                     "first_name=Example&last_name=User",
-                                     &BodyHandler);
+        let form = Form::new()
+            .field("first_name", "Example")
+            .field("last_name", "User");
+        let response = RequestBuilder::post("http://localhost:3000/users")
+            .form(form)
+            .send(&BodyHandler);
         let result = response::extract_body_to_bytes(response.unwrap());
 
         assert_eq!(result, b"Example User");
@@ -35,9 +35,9 @@ mod test {
     use iron::Headers;
     use iron::prelude::*;
 
-    use iron_test::{request, response};
+    use iron_test::{request, response, RequestBuilder};
 
-    use router::Router;
+    use router::{Params, Router};
 
     use super::{app_router, RouterHandler};
 
@@ -46,7 +46,20 @@ mod test {
         let response = request::get("http://localhost:3000/1",
                                     Headers::new(),
                                     &app_router());
-        let result = extract_body_to_bytes(response.unwrap());
+        let result = response::extract_body_to_bytes(response.unwrap());
+
+        assert_eq!(result, b"1");
+    }
+
+    #[test]
+    fn test_handler_without_router() {
+        let mut params = Params::new();
+        params.insert("id".to_owned(), "1".to_owned());
+
+        let response = RequestBuilder::get("http://localhost:3000/1")
+            .extension::<Router>(params)
+            .send(&RouterHandler);
+        let result = response::extract_body_to_bytes(response.unwrap());
 
         assert_eq!(result, b"1");
     }
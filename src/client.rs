@@ -0,0 +1,283 @@
+use iron::headers::{Header, HeaderFormat};
+use iron::prelude::*;
+use iron::{Handler, Headers, method};
+
+use url::Url;
+
+use std::cell::{Ref, RefCell, RefMut};
+use std::net::SocketAddr;
+
+use super::cookie_jar::CookieJar;
+use super::error::TestError;
+use super::request::RequestBuilder;
+use super::request_body::RequestBody;
+
+/// A client which makes requests to a single Iron Handler.
+///
+/// Paths are resolved against the base URL of the client, and every request
+/// is sent with the default headers and peer address of the client.
+///
+/// The client keeps a `CookieJar` for the session: cookies set by responses
+/// are sent with later requests they match.
+///
+/// The client can either own its Handler, or borrow one which is shared
+/// between tests.
+pub struct TestClient<'a> {
+    handler: ClientHandler<'a>,
+    base_url: String,
+    headers: Headers,
+    remote_addr: SocketAddr,
+    cookies: RefCell<CookieJar>,
+}
+
+enum ClientHandler<'a> {
+    Owned(Box<Handler>),
+    Borrowed(&'a Handler),
+}
+
+impl TestClient<'static> {
+    /// Create a client which owns the given Handler, and resolves paths
+    /// against the given base URL.
+    pub fn new<H: Handler>(handler: H, base_url: &str) -> TestClient<'static> {
+        TestClient::with_handler(ClientHandler::Owned(Box::new(handler)), base_url)
+    }
+}
+
+impl<'a> TestClient<'a> {
+    /// Create a client which borrows the given Handler, and resolves paths
+    /// against the given base URL.
+    pub fn borrowed<H: Handler>(handler: &'a H, base_url: &str) -> TestClient<'a> {
+        TestClient::with_handler(ClientHandler::Borrowed(handler), base_url)
+    }
+
+    fn with_handler(handler: ClientHandler<'a>, base_url: &str) -> TestClient<'a> {
+        TestClient {
+            handler: handler,
+            base_url: base_url.to_owned(),
+            headers: Headers::new(),
+            remote_addr: "127.0.0.1:3000".parse().unwrap(),
+            cookies: RefCell::new(CookieJar::new()),
+        }
+    }
+
+    /// Set a header which is sent with every request made by the client.
+    pub fn header<H: Header + HeaderFormat>(mut self, header: H) -> TestClient<'a> {
+        self.headers.set(header);
+        self
+    }
+
+    /// Set the address of the client making each request.
+    pub fn remote_addr(mut self, addr: SocketAddr) -> TestClient<'a> {
+        self.remote_addr = addr;
+        self
+    }
+
+    /// The base URL of the client.
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Resolve a path against the base URL of the client.
+    ///
+    /// Paths are resolved as links in a document at the base URL would be, so
+    /// an absolute path replaces the path of the base URL and a full URL
+    /// replaces it entirely.
+    pub fn url(&self, path: &str) -> String {
+        match Url::parse(&self.base_url).and_then(|base| base.join(path)) {
+            Ok(url) => url.into_string(),
+            // Leave the URL for the request to report as invalid.
+            Err(_) => format!("{}{}", self.base_url, path),
+        }
+    }
+
+    /// The cookies recorded during the session.
+    pub fn cookies(&self) -> Ref<'_, CookieJar> {
+        self.cookies.borrow()
+    }
+
+    /// The cookies recorded during the session, to inject or clear cookies.
+    pub fn cookies_mut(&self) -> RefMut<'_, CookieJar> {
+        self.cookies.borrow_mut()
+    }
+
+    /// Create a builder for a request to the given path, with the default
+    /// headers and peer address of the client.
+    pub fn request(&self, method: method::Method, path: &str) -> RequestBuilder {
+        RequestBuilder::new(method, &self.url(path))
+            .headers(self.headers.clone())
+            .remote_addr(self.remote_addr)
+    }
+
+    /// Send a request built by the client to its Handler.
+    ///
+    /// Panics if the request cannot be constructed; see `try_send`.
+    pub fn send(&self, request: &RequestBuilder) -> IronResult<Response> {
+        match self.try_send(request) {
+            Ok(response) => Ok(response),
+            Err(TestError::Handler { error, .. }) => Err(error),
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Send a request built by the client to its Handler, returning an error
+    /// rather than panicking if the request cannot be constructed.
+    pub fn try_send(&self, request: &RequestBuilder) -> Result<Response, TestError> {
+        let url = request.url_string().to_owned();
+        let mut headers = request.header_map().clone();
+        self.cookies.borrow().apply(&url, &mut headers);
+
+        let result = request.clone().headers(headers).try_send(self.handler());
+
+        match result {
+            Ok(ref response) => self.cookies.borrow_mut().store(&url, &response.headers),
+            Err(TestError::Handler { ref error, .. }) => {
+                self.cookies.borrow_mut().store(&url, &error.response.headers)
+            },
+            Err(_) => (),
+        }
+        result
+    }
+
+    /// Make a GET request to the given path.
+    pub fn get(&self, path: &str) -> IronResult<Response> {
+        self.send(&self.request(method::Get, path))
+    }
+
+    /// Make a POST request with a body to the given path.
+    pub fn post<B: Into<RequestBody>>(&self, path: &str, body: B) -> IronResult<Response> {
+        self.send(&self.request(method::Post, path).body(body))
+    }
+
+    /// Make a PATCH request with a body to the given path.
+    pub fn patch<B: Into<RequestBody>>(&self, path: &str, body: B) -> IronResult<Response> {
+        self.send(&self.request(method::Patch, path).body(body))
+    }
+
+    /// Make a PUT request with a body to the given path.
+    pub fn put<B: Into<RequestBody>>(&self, path: &str, body: B) -> IronResult<Response> {
+        self.send(&self.request(method::Put, path).body(body))
+    }
+
+    /// Make a DELETE request to the given path.
+    pub fn delete(&self, path: &str) -> IronResult<Response> {
+        self.send(&self.request(method::Delete, path))
+    }
+
+    /// Make an OPTIONS request to the given path.
+    pub fn options(&self, path: &str) -> IronResult<Response> {
+        self.send(&self.request(method::Options, path))
+    }
+
+    /// Make a HEAD request to the given path.
+    pub fn head(&self, path: &str) -> IronResult<Response> {
+        self.send(&self.request(method::Head, path))
+    }
+
+    fn handler(&self) -> &Handler {
+        match self.handler {
+            ClientHandler::Owned(ref handler) => &**handler,
+            ClientHandler::Borrowed(handler) => handler,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use iron::headers::{Authorization, Bearer, Cookie, SetCookie};
+    use iron::prelude::*;
+    use iron::{Handler, status};
+
+    use response::extract_body_to_string;
+
+    use super::*;
+
+    struct EchoHandler;
+
+    impl Handler for EchoHandler {
+        fn handle(&self, req: &mut Request) -> IronResult<Response> {
+            let token = req.headers.get::<Authorization<Bearer>>()
+                .map(|auth| auth.token.clone())
+                .unwrap_or_default();
+            let body = format!("{} {} {} {}", req.method, req.url, token, req.remote_addr);
+            Ok(Response::with((status::Ok, body)))
+        }
+    }
+
+    struct SessionHandler;
+
+    impl Handler for SessionHandler {
+        fn handle(&self, req: &mut Request) -> IronResult<Response> {
+            let mut response = Response::with(status::Ok);
+            if req.url.path() == ["login"] {
+                response.headers.set(SetCookie(vec!["session=abc; Path=/; HttpOnly".to_owned()]));
+            } else if let Some(cookies) = req.headers.get::<Cookie>() {
+                response.body = Some(Box::new(cookies.join("; ")));
+            }
+            Ok(response)
+        }
+    }
+
+    #[test]
+    fn test_relative_paths() {
+        let client = TestClient::new(EchoHandler, "http://localhost:3000/api/");
+
+        let response = client.get("users/1").unwrap();
+        assert_eq!(extract_body_to_string(response),
+                   "GET http://localhost:3000/api/users/1  127.0.0.1:3000");
+
+        let response = client.delete("/health").unwrap();
+        assert_eq!(extract_body_to_string(response),
+                   "DELETE http://localhost:3000/health  127.0.0.1:3000");
+
+        let response = client.get("https://example.com/").unwrap();
+        assert_eq!(extract_body_to_string(response),
+                   "GET https://example.com/  127.0.0.1:3000");
+    }
+
+    #[test]
+    fn test_defaults() {
+        let handler = EchoHandler;
+        let client = TestClient::borrowed(&handler, "http://localhost:3000")
+            .header(Authorization(Bearer { token: "secret".to_owned() }))
+            .remote_addr("10.0.0.1:4321".parse().unwrap());
+
+        let response = client.post("/users", "name=Example").unwrap();
+        assert_eq!(extract_body_to_string(response),
+                   "POST http://localhost:3000/users secret 10.0.0.1:4321");
+
+        let request = client.request(method::Get, "/users")
+            .header(Authorization(Bearer { token: "other".to_owned() }));
+        let response = client.send(&request).unwrap();
+        assert_eq!(extract_body_to_string(response),
+                   "GET http://localhost:3000/users other 10.0.0.1:4321");
+    }
+
+    #[test]
+    fn test_invalid_base_url() {
+        let client = TestClient::new(EchoHandler, "localhost:3000");
+        let err = client.try_send(&client.request(method::Get, "/users")).unwrap_err();
+
+        assert_eq!(err.request(), "GET localhost:3000/users");
+    }
+
+    #[test]
+    fn test_cookies() {
+        let client = TestClient::new(SessionHandler, "http://localhost:3000");
+
+        client.post("/login", "user=example").unwrap();
+        assert_eq!(client.cookies().get("session").unwrap().value, "abc");
+
+        let response = client.get("/account").unwrap();
+        assert_eq!(extract_body_to_string(response), "session=abc");
+
+        client.cookies_mut().set("http://localhost:3000/", "theme=dark");
+        let request = client.request(method::Get, "/account")
+            .header(Cookie(vec!["session=override".to_owned()]));
+        let response = client.send(&request).unwrap();
+        assert_eq!(extract_body_to_string(response), "session=override; theme=dark");
+
+        client.cookies_mut().clear();
+        let response = client.get("/account").unwrap();
+        assert_eq!(extract_body_to_string(response), "");
+    }
+}
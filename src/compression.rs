@@ -0,0 +1,44 @@
+use brotli::Decompressor;
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+
+use iron::headers::{ContentEncoding, Encoding, Headers};
+
+use std::io::{self, Read};
+
+// Decode a body according to the Content-Encoding header, undoing each
+// encoding in the reverse of the order it was applied.
+pub fn decode(headers: &Headers, body: &[u8]) -> io::Result<Vec<u8>> {
+    let encodings = match headers.get::<ContentEncoding>() {
+        Some(&ContentEncoding(ref encodings)) => encodings.clone(),
+        None => vec![],
+    };
+
+    let mut body = body.to_vec();
+    for encoding in encodings.iter().rev() {
+        body = try!(decode_one(encoding, &body));
+    }
+    Ok(body)
+}
+
+fn decode_one(encoding: &Encoding, body: &[u8]) -> io::Result<Vec<u8>> {
+    let mut result = Vec::new();
+    match *encoding {
+        Encoding::Identity => result.extend_from_slice(body),
+        Encoding::Gzip => { try!(GzDecoder::new(body).read_to_end(&mut result)); },
+        Encoding::Deflate => {
+            // Deflate should be zlib-wrapped, but some servers send it raw.
+            if ZlibDecoder::new(body).read_to_end(&mut result).is_err() {
+                result.clear();
+                try!(DeflateDecoder::new(body).read_to_end(&mut result));
+            }
+        },
+        Encoding::EncodingExt(ref ext) if ext.eq_ignore_ascii_case("br") => {
+            try!(Decompressor::new(body, 4096).read_to_end(&mut result));
+        },
+        ref encoding => {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("unsupported Content-Encoding `{}`", encoding)));
+        },
+    }
+    Ok(result)
+}
@@ -0,0 +1,364 @@
+use iron::headers::{self, Headers, HttpDate};
+
+use time;
+use url::Url;
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A cookie recorded by a `CookieJar`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cookie {
+    /// The name of the cookie.
+    pub name: String,
+    /// The value of the cookie.
+    pub value: String,
+    /// The domain the cookie is sent to.
+    pub domain: String,
+    /// Whether the cookie is only sent to exactly `domain`, rather than to
+    /// its subdomains as well. True when the cookie had no Domain attribute.
+    pub host_only: bool,
+    /// The path the cookie is sent to, along with the paths below it.
+    pub path: String,
+    /// When the cookie expires, if it is not a session cookie.
+    pub expires: Option<SystemTime>,
+    /// Whether the cookie is only sent over https.
+    pub secure: bool,
+    /// Whether the cookie is hidden from scripts.
+    pub http_only: bool,
+}
+
+impl Cookie {
+    /// Create a session cookie which is sent to every path of the given domain.
+    pub fn new(name: &str, value: &str, domain: &str) -> Cookie {
+        Cookie {
+            name: name.to_owned(),
+            value: value.to_owned(),
+            domain: domain.to_lowercase(),
+            host_only: true,
+            path: "/".to_owned(),
+            expires: None,
+            secure: false,
+            http_only: false,
+        }
+    }
+
+    /// Parse a Set-Cookie header value received in response to a request
+    /// for the given URL.
+    ///
+    /// Returns `None` if the header is invalid, or sets a cookie for a
+    /// domain the URL does not belong to.
+    pub fn parse(set_cookie: &str, url: &Url) -> Option<Cookie> {
+        let host = match url.host_str() {
+            Some(host) => host.to_lowercase(),
+            None => return None,
+        };
+
+        let mut parts = set_cookie.split(';');
+        let (name, value) = match parts.next().and_then(|pair| split_pair(pair)) {
+            Some((name, value)) if !name.is_empty() => (name, value),
+            _ => return None,
+        };
+
+        let mut cookie = Cookie::new(name, value, &host);
+        cookie.path = default_path(url);
+        let mut max_age = None;
+
+        for attribute in parts {
+            let (key, value) = split_pair(attribute).unwrap_or((attribute.trim(), ""));
+            match &*key.to_lowercase() {
+                "domain" if !value.is_empty() => {
+                    let domain = value.trim_left_matches('.').to_lowercase();
+                    if !domain_matches(&host, &domain) {
+                        return None;
+                    }
+                    cookie.domain = domain;
+                    cookie.host_only = false;
+                },
+                "path" if value.starts_with('/') => cookie.path = value.to_owned(),
+                "expires" => {
+                    if let Ok(date) = value.parse::<HttpDate>() {
+                        cookie.expires = Some(to_system_time(date.0.to_timespec()));
+                    }
+                },
+                "max-age" => {
+                    if let Ok(seconds) = value.parse::<i64>() {
+                        max_age = Some(seconds);
+                    }
+                },
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                _ => (),
+            }
+        }
+
+        // Max-Age takes precedence over Expires. A Max-Age too large to be
+        // represented never expires.
+        if let Some(seconds) = max_age {
+            cookie.expires = if seconds <= 0 {
+                Some(UNIX_EPOCH)
+            } else {
+                SystemTime::now().checked_add(Duration::from_secs(seconds as u64))
+            };
+        }
+
+        Some(cookie)
+    }
+
+    /// Whether the cookie has expired.
+    pub fn is_expired(&self) -> bool {
+        self.expires.map_or(false, |expires| expires <= SystemTime::now())
+    }
+
+    /// Whether the cookie is sent with a request for the given URL.
+    pub fn matches(&self, url: &Url) -> bool {
+        let host = match url.host_str() {
+            Some(host) => host.to_lowercase(),
+            None => return false,
+        };
+        let domain_ok = if self.host_only {
+            host == self.domain
+        } else {
+            domain_matches(&host, &self.domain)
+        };
+
+        domain_ok && path_matches(url.path(), &self.path) &&
+            (!self.secure || url.scheme() == "https") && !self.is_expired()
+    }
+}
+
+/// A store of cookies which persists them across requests.
+///
+/// Cookies set by responses are recorded with `store`, and the cookies which
+/// match a later request are attached to it by `apply`, honouring the Domain,
+/// Path, Expires, Max-Age and Secure attributes of each cookie.
+#[derive(Debug, Clone, Default)]
+pub struct CookieJar {
+    cookies: Vec<Cookie>,
+}
+
+impl CookieJar {
+    /// Create an empty cookie jar.
+    pub fn new() -> CookieJar {
+        CookieJar { cookies: vec![] }
+    }
+
+    /// Record the cookies set by the headers of a response to a request for
+    /// the given URL.
+    pub fn store(&mut self, url: &str, headers: &Headers) {
+        let url = match Url::parse(url) {
+            Ok(url) => url,
+            Err(_) => return,
+        };
+
+        if let Some(&headers::SetCookie(ref set_cookies)) = headers.get::<headers::SetCookie>() {
+            for set_cookie in set_cookies {
+                match Cookie::parse(set_cookie, &url) {
+                    Some(cookie) => self.insert(cookie),
+                    None => debug!("Ignoring invalid cookie; set_cookie = {:?}, url = {}", set_cookie, url),
+                }
+            }
+        }
+    }
+
+    /// Record a cookie as if it had been set by a response to a request for
+    /// the given URL.
+    pub fn set(&mut self, url: &str, set_cookie: &str) {
+        if let Some(cookie) = Url::parse(url).ok().and_then(|url| Cookie::parse(set_cookie, &url)) {
+            self.insert(cookie);
+        }
+    }
+
+    /// Insert a cookie, replacing any cookie with the same name, domain and
+    /// path. Inserting an expired cookie removes the cookie it replaces.
+    pub fn insert(&mut self, cookie: Cookie) {
+        self.cookies.retain(|c| {
+            !(c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path)
+        });
+        if !cookie.is_expired() {
+            self.cookies.push(cookie);
+        }
+    }
+
+    /// Attach the cookies which match the given URL to the Cookie header of
+    /// a request. Cookies already in the header take precedence over cookies
+    /// of the same name in the jar.
+    pub fn apply(&self, url: &str, headers: &mut Headers) {
+        let cookies = self.matching(url);
+        if cookies.is_empty() {
+            return;
+        }
+
+        let mut pairs = headers.get::<headers::Cookie>().map(|c| c.0.clone()).unwrap_or_default();
+        let given = pairs.iter()
+            .map(|pair| pair.split('=').next().unwrap_or("").trim().to_owned())
+            .collect::<Vec<_>>();
+        // As in a browser, every matching cookie is sent, even when several
+        // share a name.
+        for cookie in cookies {
+            if !given.contains(&cookie.name) {
+                pairs.push(format!("{}={}", cookie.name, cookie.value));
+            }
+        }
+        headers.set(headers::Cookie(pairs));
+    }
+
+    /// The cookies which would be sent with a request for the given URL,
+    /// with the most specific paths first.
+    pub fn matching(&self, url: &str) -> Vec<&Cookie> {
+        let url = match Url::parse(url) {
+            Ok(url) => url,
+            Err(_) => return vec![],
+        };
+        let mut cookies: Vec<&Cookie> = self.cookies.iter().filter(|c| c.matches(&url)).collect();
+        cookies.sort_by(|a, b| b.path.len().cmp(&a.path.len()));
+        cookies
+    }
+
+    /// The unexpired cookie with the given name, if there is one.
+    pub fn get(&self, name: &str) -> Option<&Cookie> {
+        self.iter().find(|c| c.name == name)
+    }
+
+    /// Iterate over the unexpired cookies in the jar.
+    pub fn iter<'a>(&'a self) -> Box<Iterator<Item = &'a Cookie> + 'a> {
+        Box::new(self.cookies.iter().filter(|c| !c.is_expired()))
+    }
+
+    /// Remove every cookie with the given name.
+    pub fn remove(&mut self, name: &str) {
+        self.cookies.retain(|c| c.name != name);
+    }
+
+    /// Remove every cookie from the jar.
+    pub fn clear(&mut self) {
+        self.cookies.clear();
+    }
+}
+
+fn split_pair(pair: &str) -> Option<(&str, &str)> {
+    pair.find('=').map(|i| (pair[..i].trim(), pair[i + 1..].trim()))
+}
+
+// The default path of a cookie is the directory of the request path.
+fn default_path(url: &Url) -> String {
+    let path = url.path();
+    match path.rfind('/') {
+        Some(0) | None => "/".to_owned(),
+        Some(i) => path[..i].to_owned(),
+    }
+}
+
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain || (host.ends_with(domain) && host[..host.len() - domain.len()].ends_with('.'))
+}
+
+fn path_matches(path: &str, cookie_path: &str) -> bool {
+    path == cookie_path ||
+        (path.starts_with(cookie_path) &&
+         (cookie_path.ends_with('/') || path[cookie_path.len()..].starts_with('/')))
+}
+
+fn to_system_time(timespec: time::Timespec) -> SystemTime {
+    if timespec.sec < 0 {
+        UNIX_EPOCH
+    } else {
+        UNIX_EPOCH + Duration::new(timespec.sec as u64, timespec.nsec as u32)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use iron::headers::{Headers, SetCookie};
+
+    use super::*;
+
+    fn jar_with(url: &str, set_cookies: &[&str]) -> CookieJar {
+        let mut headers = Headers::new();
+        headers.set(SetCookie(set_cookies.iter().map(|s| s.to_string()).collect()));
+        let mut jar = CookieJar::new();
+        jar.store(url, &headers);
+        jar
+    }
+
+    fn names(jar: &CookieJar, url: &str) -> Vec<String> {
+        jar.matching(url).iter().map(|c| c.name.clone()).collect()
+    }
+
+    #[test]
+    fn test_path() {
+        let jar = jar_with("http://localhost:3000/account/login",
+                           &["session=abc", "admin=1; Path=/admin", "root=1; Path=/"]);
+
+        assert_eq!(names(&jar, "http://localhost:3000/account/settings"), vec!["session", "root"]);
+        assert_eq!(names(&jar, "http://localhost:3000/admin/users"), vec!["admin", "root"]);
+        assert_eq!(names(&jar, "http://localhost:3000/administrator"), vec!["root"]);
+    }
+
+    #[test]
+    fn test_domain() {
+        let jar = jar_with("http://www.example.com/",
+                           &["host=1", "shared=1; Domain=.example.com", "other=1; Domain=example.org"]);
+
+        assert_eq!(names(&jar, "http://www.example.com/"), vec!["host", "shared"]);
+        assert_eq!(names(&jar, "http://api.example.com/"), vec!["shared"]);
+        assert!(jar.get("other").is_none());
+    }
+
+    #[test]
+    fn test_host_only_and_domain_cookies() {
+        // A cookie is identified by its name, domain and path, so a domain
+        // cookie replaces a host-only cookie of the same name.
+        let jar = jar_with("http://localhost/", &["id=old", "id=new; Domain=localhost"]);
+        let mut headers = Headers::new();
+        jar.apply("http://localhost/", &mut headers);
+
+        assert_eq!(headers.get::<headers::Cookie>().unwrap().0, vec!["id=new"]);
+        assert!(!jar.get("id").unwrap().host_only);
+    }
+
+    #[test]
+    fn test_apply_same_name_on_different_paths() {
+        let jar = jar_with("http://localhost/", &["id=root; Path=/", "id=admin; Path=/admin"]);
+        let mut headers = Headers::new();
+        jar.apply("http://localhost/admin/x", &mut headers);
+
+        assert_eq!(headers.get::<headers::Cookie>().unwrap().0, vec!["id=admin", "id=root"]);
+    }
+
+    #[test]
+    fn test_large_max_age() {
+        let jar = jar_with("http://localhost/", &["id=x; Max-Age=9223372036854775807"]);
+
+        assert!(!jar.get("id").unwrap().is_expired());
+        assert_eq!(names(&jar, "http://localhost/"), vec!["id"]);
+    }
+
+    #[test]
+    fn test_secure_and_http_only() {
+        let jar = jar_with("https://localhost/", &["token=abc; Secure; HttpOnly"]);
+
+        assert!(jar.get("token").unwrap().http_only);
+        assert_eq!(names(&jar, "https://localhost/"), vec!["token"]);
+        assert!(names(&jar, "http://localhost/").is_empty());
+    }
+
+    #[test]
+    fn test_expiry() {
+        let mut jar = jar_with("http://localhost/",
+                               &["a=1; Max-Age=3600", "b=1; Expires=Sun, 06 Nov 1994 08:49:37 GMT", "c=1"]);
+        assert_eq!(names(&jar, "http://localhost/"), vec!["a", "c"]);
+
+        jar.set("http://localhost/", "a=; Max-Age=0");
+        assert_eq!(names(&jar, "http://localhost/"), vec!["c"]);
+    }
+
+    #[test]
+    fn test_apply() {
+        let jar = jar_with("http://localhost/", &["a=1", "b=2"]);
+        let mut headers = Headers::new();
+        headers.set(headers::Cookie(vec!["b=override".to_owned()]));
+        jar.apply("http://localhost/", &mut headers);
+
+        assert_eq!(headers.get::<headers::Cookie>().unwrap().0, vec!["b=override", "a=1"]);
+    }
+}
@@ -0,0 +1,206 @@
+use hyper;
+use iron::IronError;
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// An error encountered while making a request to an Iron Handler.
+///
+/// Every variant records the offending request, formatted as its method and
+/// URL, so that a failure can be traced back to the test which caused it.
+#[derive(Debug)]
+pub enum TestError {
+    /// The URL of the request could not be parsed.
+    InvalidUrl {
+        /// The offending request.
+        request: String,
+        /// Why the URL could not be parsed.
+        reason: String,
+    },
+
+    /// The URL of the request has a scheme other than `http` or `https`.
+    UnsupportedScheme {
+        /// The offending request.
+        request: String,
+        /// The scheme of the URL.
+        scheme: String,
+    },
+
+    /// A header of the request cannot be written as part of an HTTP request.
+    MalformedHeader {
+        /// The offending request.
+        request: String,
+        /// The name of the offending header.
+        header: String,
+    },
+
+    /// The method of the request is not a valid method name, or does not
+    /// allow the request to have a body.
+    InvalidMethod {
+        /// The offending request.
+        request: String,
+        /// Why the method is invalid.
+        reason: String,
+    },
+
+    /// The body of the request could not be read, because its file could not
+    /// be opened or its reader was already sent with another request.
+    Body {
+        /// The offending request.
+        request: String,
+        /// The error encountered while reading the body.
+        error: io::Error,
+    },
+
+    /// Hyper failed to parse the request.
+    Parse {
+        /// The offending request.
+        request: String,
+        /// The error returned by hyper.
+        error: hyper::Error,
+    },
+
+    /// Iron failed to construct a Request from the parsed request.
+    InvalidRequest {
+        /// The offending request.
+        request: String,
+        /// The reason given by Iron.
+        reason: String,
+    },
+
+    /// The Handler returned an error.
+    Handler {
+        /// The offending request.
+        request: String,
+        /// The error returned by the Handler.
+        error: IronError,
+    },
+}
+
+impl TestError {
+    /// The offending request, formatted as its method and URL.
+    pub fn request(&self) -> &str {
+        match *self {
+            TestError::InvalidUrl { ref request, .. } |
+            TestError::UnsupportedScheme { ref request, .. } |
+            TestError::MalformedHeader { ref request, .. } |
+            TestError::InvalidMethod { ref request, .. } |
+            TestError::Body { ref request, .. } |
+            TestError::Parse { ref request, .. } |
+            TestError::InvalidRequest { ref request, .. } |
+            TestError::Handler { ref request, .. } => request,
+        }
+    }
+}
+
+impl fmt::Display for TestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TestError::InvalidUrl { ref request, ref reason } =>
+                write!(f, "invalid URL in request `{}`: {}", request, reason),
+            TestError::UnsupportedScheme { ref request, ref scheme } =>
+                write!(f, "unsupported scheme `{}` in request `{}`; expected http or https", scheme, request),
+            TestError::MalformedHeader { ref request, ref header } =>
+                write!(f, "malformed header {:?} in request `{}`", header, request),
+            TestError::InvalidMethod { ref request, ref reason } =>
+                write!(f, "invalid method in request `{}`: {}", request, reason),
+            TestError::Body { ref request, ref error } =>
+                write!(f, "failed to read the body of request `{}`: {}", request, error),
+            TestError::Parse { ref request, ref error } =>
+                write!(f, "hyper failed to parse request `{}`: {}", request, error),
+            TestError::InvalidRequest { ref request, ref reason } =>
+                write!(f, "iron failed to construct request `{}`: {}", request, reason),
+            TestError::Handler { ref request, ref error } =>
+                write!(f, "handler returned an error for request `{}`: {}", request, error),
+        }
+    }
+}
+
+impl Error for TestError {
+    fn description(&self) -> &str {
+        match *self {
+            TestError::InvalidUrl { .. } => "invalid URL",
+            TestError::UnsupportedScheme { .. } => "unsupported scheme",
+            TestError::MalformedHeader { .. } => "malformed header",
+            TestError::InvalidMethod { .. } => "invalid method",
+            TestError::Body { .. } => "failed to read request body",
+            TestError::Parse { .. } => "failed to parse request",
+            TestError::InvalidRequest { .. } => "failed to construct request",
+            TestError::Handler { .. } => "handler returned an error",
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            TestError::Body { ref error, .. } => Some(error),
+            TestError::Parse { ref error, .. } => Some(error),
+            TestError::Handler { ref error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// An error encountered while decoding a response body to text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    /// The charset of the response is not supported.
+    UnsupportedCharset(String),
+
+    /// The body is not valid in the charset of the response. Never returned
+    /// for utf8, which is decoded lossily instead.
+    Malformed {
+        /// The charset of the response.
+        charset: String,
+        /// Why the body could not be decoded.
+        reason: String,
+    },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::UnsupportedCharset(ref charset) =>
+                write!(f, "unsupported charset `{}` in response Content-Type", charset),
+            DecodeError::Malformed { ref charset, ref reason } =>
+                write!(f, "response body is not valid {}: {}", charset, reason),
+        }
+    }
+}
+
+impl Error for DecodeError {
+    fn description(&self) -> &str {
+        match *self {
+            DecodeError::UnsupportedCharset(_) => "unsupported charset",
+            DecodeError::Malformed { .. } => "malformed response body",
+        }
+    }
+}
+
+/// An error returned by the body of a response while it was being written.
+///
+/// Keeps the bytes written before the error, so that tests can check how far
+/// a streaming or file-backed body got.
+#[derive(Debug)]
+pub struct BodyError {
+    /// The bytes written before the error.
+    pub bytes: Vec<u8>,
+    /// The error returned by the body.
+    pub error: io::Error,
+}
+
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failed to write response body after {} bytes: {}", self.bytes.len(), self.error)
+    }
+}
+
+impl Error for BodyError {
+    fn description(&self) -> &str {
+        "failed to write response body"
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        Some(&self.error)
+    }
+}
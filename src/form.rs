@@ -0,0 +1,64 @@
+use iron::mime::{Mime, SubLevel, TopLevel};
+
+use url::form_urlencoded;
+
+/// A builder for `application/x-www-form-urlencoded` request bodies.
+///
+/// Keys and values are percent-encoded, and fields are written in the order
+/// they are added, so a key can be repeated. Pass the builder to
+/// `RequestBuilder::form` to set it as the body of a request, along with the
+/// matching Content-Type header.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Form {
+    fields: Vec<(String, String)>,
+}
+
+impl Form {
+    /// Create an empty form.
+    pub fn new() -> Form {
+        Form { fields: vec![] }
+    }
+
+    /// Add a field to the form.
+    pub fn field(mut self, key: &str, value: &str) -> Form {
+        self.fields.push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// The Content-Type of the body, `application/x-www-form-urlencoded`.
+    pub fn content_type(&self) -> Mime {
+        Mime(TopLevel::Application, SubLevel::WwwFormUrlEncoded, vec![])
+    }
+
+    /// The percent-encoded body.
+    pub fn encode(&self) -> String {
+        form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(self.fields.iter())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let form = Form::new()
+            .field("first_name", "Example")
+            .field("last_name", "User");
+
+        assert_eq!(form.encode(), "first_name=Example&last_name=User");
+        assert_eq!(form.content_type().to_string(), "application/x-www-form-urlencoded");
+    }
+
+    #[test]
+    fn test_encode_repeated_and_non_ascii() {
+        let form = Form::new()
+            .field("tag", "a&b")
+            .field("tag", "50% off")
+            .field("name", "Zo\u{eb} \u{5f20}");
+
+        assert_eq!(form.encode(), "tag=a%26b&tag=50%25+off&name=Zo%C3%AB+%E5%BC%A0");
+    }
+}
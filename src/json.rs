@@ -0,0 +1,333 @@
+use serde::de::DeserializeOwned;
+use serde_json::{self, Value};
+
+use std::error::Error;
+use std::fmt;
+
+/// An error encountered while decoding a response body as JSON.
+///
+/// Shows the raw body alongside the error from serde_json, so that a failing
+/// test explains what the Handler actually returned.
+#[derive(Debug)]
+pub struct JsonError {
+    /// The error returned by serde_json.
+    pub error: serde_json::Error,
+    /// The raw body which failed to decode.
+    pub body: Vec<u8>,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failed to decode response body as JSON: {}\n\nbody:\n{}",
+               self.error, String::from_utf8_lossy(&self.body))
+    }
+}
+
+impl Error for JsonError {
+    fn description(&self) -> &str {
+        "failed to decode response body as JSON"
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        Some(&self.error)
+    }
+}
+
+// Decode a body as JSON, keeping the body for the error if decoding fails.
+pub fn from_body<T: DeserializeOwned>(body: &[u8]) -> Result<T, JsonError> {
+    serde_json::from_slice(body).map_err(|err| JsonError { error: err, body: body.to_vec() })
+}
+
+/// Compares JSON documents structurally, reporting each difference by path.
+///
+/// By default the documents must be equal. In subset mode, objects may have
+/// keys which are not expected, and arrays may have elements beyond those
+/// expected. Volatile values such as ids and timestamps can be ignored by
+/// path, such as `$.users[*].id`, where `[*]` matches any array index and
+/// `.*` matches any key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonMatcher {
+    expected: Value,
+    subset: bool,
+    ignored: Vec<Vec<Segment>>,
+}
+
+/// A difference between an expected and an actual JSON document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonDiff {
+    /// The path of the difference, such as `$.users[2].email`.
+    pub path: String,
+    /// The expected value, or `None` if the value was not expected.
+    pub expected: Option<Value>,
+    /// The actual value, or `None` if the value is missing.
+    pub actual: Option<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+    AnyKey,
+    AnyIndex,
+}
+
+impl JsonMatcher {
+    /// Create a matcher which compares documents to the expected document.
+    pub fn new(expected: Value) -> JsonMatcher {
+        JsonMatcher { expected: expected, subset: false, ignored: vec![] }
+    }
+
+    /// Only require the actual document to contain the expected document.
+    pub fn subset(mut self) -> JsonMatcher {
+        self.subset = true;
+        self
+    }
+
+    /// Ignore the values at the given path, such as `$.users[*].id`.
+    ///
+    /// Panics if the path is not valid.
+    pub fn ignore(mut self, path: &str) -> JsonMatcher {
+        match parse_path(path) {
+            Some(segments) => self.ignored.push(segments),
+            None => panic!("invalid JSON path {:?}", path),
+        }
+        self
+    }
+
+    /// The differences between the actual document and the expected document.
+    pub fn diff(&self, actual: &Value) -> Vec<JsonDiff> {
+        let mut diffs = vec![];
+        self.diff_at(&mut vec![], Some(&self.expected), Some(actual), &mut diffs);
+        diffs
+    }
+
+    /// Assert that the actual document matches the expected document,
+    /// printing every difference along with the actual document on failure.
+    pub fn assert_matches(&self, actual: &Value) {
+        if let Some(report) = self.report(actual) {
+            let actual = serde_json::to_string_pretty(actual).unwrap_or_default();
+            panic!("{}\n\nactual:\n{}", report, actual);
+        }
+    }
+
+    // A report of the differences, if there are any.
+    pub(crate) fn report(&self, actual: &Value) -> Option<String> {
+        let diffs = self.diff(actual);
+        if diffs.is_empty() {
+            return None;
+        }
+        let diffs: Vec<String> = diffs.iter().map(|diff| format!("  {}", diff)).collect();
+        Some(format!("JSON mismatch:\n{}", diffs.join("\n")))
+    }
+
+    fn diff_at(&self,
+               path: &mut Vec<Segment>,
+               expected: Option<&Value>,
+               actual: Option<&Value>,
+               diffs: &mut Vec<JsonDiff>) {
+        if self.ignored.iter().any(|pattern| path_matches(pattern, path)) {
+            return;
+        }
+
+        match (expected, actual) {
+            (Some(&Value::Object(ref expected)), Some(&Value::Object(ref actual))) => {
+                for (key, value) in expected.iter() {
+                    path.push(Segment::Key(key.clone()));
+                    self.diff_at(path, Some(value), actual.get(key), diffs);
+                    path.pop();
+                }
+                if !self.subset {
+                    for (key, value) in actual.iter().filter(|&(key, _)| !expected.contains_key(key)) {
+                        path.push(Segment::Key(key.clone()));
+                        self.diff_at(path, None, Some(value), diffs);
+                        path.pop();
+                    }
+                }
+            },
+            (Some(&Value::Array(ref expected)), Some(&Value::Array(ref actual))) => {
+                let len = if self.subset { expected.len() } else { expected.len().max(actual.len()) };
+                for i in 0..len {
+                    path.push(Segment::Index(i));
+                    self.diff_at(path, expected.get(i), actual.get(i), diffs);
+                    path.pop();
+                }
+            },
+            (expected, actual) => {
+                if expected != actual {
+                    diffs.push(JsonDiff {
+                        path: format_path(path),
+                        expected: expected.cloned(),
+                        actual: actual.cloned(),
+                    });
+                }
+            },
+        }
+    }
+}
+
+impl fmt::Display for JsonDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.expected, &self.actual) {
+            (&Some(ref expected), &Some(ref actual)) =>
+                write!(f, "{}: expected {}, got {}", self.path, expected, actual),
+            (&Some(ref expected), &None) =>
+                write!(f, "{}: expected {}, but it is missing", self.path, expected),
+            (&None, &Some(ref actual)) =>
+                write!(f, "{}: unexpected {}", self.path, actual),
+            (&None, &None) => write!(f, "{}", self.path),
+        }
+    }
+}
+
+fn path_matches(pattern: &[Segment], path: &[Segment]) -> bool {
+    pattern.len() == path.len() && pattern.iter().zip(path.iter()).all(|(p, s)| {
+        match (p, s) {
+            (&Segment::AnyKey, &Segment::Key(_)) | (&Segment::AnyIndex, &Segment::Index(_)) => true,
+            (p, s) => p == s,
+        }
+    })
+}
+
+fn format_path(path: &[Segment]) -> String {
+    let mut result = "$".to_owned();
+    for segment in path {
+        match *segment {
+            Segment::Key(ref key) if is_identifier(key) => result.push_str(&format!(".{}", key)),
+            Segment::Key(ref key) => result.push_str(&format!("[{}]", Value::String(key.clone()))),
+            Segment::Index(i) => result.push_str(&format!("[{}]", i)),
+            Segment::AnyKey => result.push_str(".*"),
+            Segment::AnyIndex => result.push_str("[*]"),
+        }
+    }
+    result
+}
+
+// Parse a path such as `$.users[*].id` or `$["content-type"]`.
+fn parse_path(path: &str) -> Option<Vec<Segment>> {
+    if !path.starts_with('$') {
+        return None;
+    }
+
+    let mut segments = vec![];
+    let mut rest = &path[1..];
+    while !rest.is_empty() {
+        if rest.starts_with(".*") {
+            segments.push(Segment::AnyKey);
+            rest = &rest[2..];
+        } else if rest.starts_with('.') {
+            let end = rest[1..].find(|c| c == '.' || c == '[').map_or(rest.len(), |i| i + 1);
+            if end == 1 {
+                return None;
+            }
+            segments.push(Segment::Key(rest[1..end].to_owned()));
+            rest = &rest[end..];
+        } else if rest.starts_with("[*]") {
+            segments.push(Segment::AnyIndex);
+            rest = &rest[3..];
+        } else if rest.starts_with("[\"") {
+            // Find the closing quote by decoding the key as a JSON string.
+            let end = match rest.find("\"]") {
+                Some(end) => end,
+                None => return None,
+            };
+            match serde_json::from_str::<String>(&rest[1..end + 1]) {
+                Ok(key) => segments.push(Segment::Key(key)),
+                Err(_) => return None,
+            }
+            rest = &rest[end + 2..];
+        } else if rest.starts_with('[') {
+            let end = match rest.find(']') {
+                Some(end) => end,
+                None => return None,
+            };
+            match rest[1..end].parse() {
+                Ok(i) => segments.push(Segment::Index(i)),
+                Err(_) => return None,
+            }
+            rest = &rest[end + 1..];
+        } else {
+            return None;
+        }
+    }
+
+    Some(segments)
+}
+
+fn is_identifier(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn diffs(matcher: &JsonMatcher, actual: Value) -> Vec<String> {
+        matcher.diff(&actual).iter().map(|diff| diff.to_string()).collect()
+    }
+
+    #[test]
+    fn test_exact() {
+        let matcher = JsonMatcher::new(json!({
+            "users": [{"name": "a", "email": "a@example.com"}, {"name": "b"}],
+            "total": 2
+        }));
+
+        assert!(diffs(&matcher, json!({
+            "users": [{"name": "a", "email": "a@example.com"}, {"name": "b"}],
+            "total": 2
+        })).is_empty());
+
+        assert_eq!(diffs(&matcher, json!({
+            "users": [{"name": "a", "email": "b@example.com"}, {"name": "b", "admin": true}, {}],
+        })), vec![
+            "$.total: expected 2, but it is missing",
+            "$.users[0].email: expected \"a@example.com\", got \"b@example.com\"",
+            "$.users[1].admin: unexpected true",
+            "$.users[2]: unexpected {}",
+        ]);
+    }
+
+    #[test]
+    fn test_subset() {
+        let matcher = JsonMatcher::new(json!({"users": [{"name": "a"}]})).subset();
+
+        assert!(diffs(&matcher, json!({
+            "users": [{"id": 1, "name": "a"}, {"id": 2, "name": "b"}],
+            "total": 2
+        })).is_empty());
+        assert_eq!(diffs(&matcher, json!({"users": []})),
+                   vec!["$.users[0]: expected {\"name\":\"a\"}, but it is missing"]);
+    }
+
+    #[test]
+    fn test_ignore() {
+        let matcher = JsonMatcher::new(json!({
+            "users": [{"id": 0, "name": "a"}, {"id": 0, "name": "b"}],
+            "meta": {"created-at": null}
+        })).ignore("$.users[*].id").ignore("$.meta[\"created-at\"]");
+
+        assert!(diffs(&matcher, json!({
+            "users": [{"id": 41, "name": "a"}, {"id": 42, "name": "b"}],
+            "meta": {"created-at": "2017-01-01T00:00:00Z"}
+        })).is_empty());
+    }
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(parse_path("$.a[0][*].*[\"b.c\"]"), Some(vec![
+            Segment::Key("a".to_owned()),
+            Segment::Index(0),
+            Segment::AnyIndex,
+            Segment::AnyKey,
+            Segment::Key("b.c".to_owned()),
+        ]));
+        assert_eq!(parse_path("a.b"), None);
+        assert_eq!(parse_path("$..b"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "JSON mismatch:\n  $.total: expected 2, got 3")]
+    fn test_assert_matches() {
+        JsonMatcher::new(json!({"total": 2})).assert_matches(&json!({"total": 3}));
+    }
+}
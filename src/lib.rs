@@ -3,15 +3,45 @@
 
 //! A set of convenience methods and constructors for making requests to Iron Handlers.
 
+extern crate encoding;
 extern crate iron;
 extern crate hyper;
+extern crate time;
 extern crate url;
 extern crate uuid;
 
 #[macro_use]
 extern crate log;
 
+#[cfg(feature = "compression")]
+extern crate brotli;
+#[cfg(feature = "compression")]
+extern crate flate2;
+
+#[cfg(feature = "json")]
+extern crate serde;
+#[cfg(feature = "json")]
+#[cfg_attr(test, macro_use)]
+extern crate serde_json;
+
+#[cfg(all(test, feature = "json"))]
+#[macro_use]
+extern crate serde_derive;
+
+pub use client::TestClient;
+pub use cookie_jar::{Cookie, CookieJar};
+pub use error::{BodyError, DecodeError, TestError};
+pub use form::Form;
+#[cfg(feature = "json")]
+pub use json::JsonError;
+pub use multipart::Multipart;
 pub use project_builder::ProjectBuilder;
+pub use raw::{RawOutcome, RawRequest};
+pub use request::RequestBuilder;
+pub use request_body::RequestBody;
+pub use response::TestResponse;
+pub use server::TestServer;
+pub use wire::WireResponse;
 
 /// Set of convenience methods for making requests to Iron Handlers.
 pub mod request;
@@ -22,4 +52,17 @@ pub mod response;
 /// Tooling for mocking a Stream.
 pub mod mock_stream;
 
+mod client;
+#[cfg(feature = "compression")]
+mod compression;
+mod cookie_jar;
+mod error;
+mod form;
+#[cfg(feature = "json")]
+mod json;
+mod multipart;
 mod project_builder;
+mod raw;
+mod request_body;
+mod server;
+mod wire;
@@ -2,44 +2,408 @@
 
 use hyper::net::NetworkStream;
 use std::any::Any;
-use std::io::{Read, Write, Result};
+use std::io::{Cursor, Error, ErrorKind, Read, Write, Result};
 use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 /// A mock network stream
 #[derive(Clone)]
 pub struct MockStream<T> {
-    data: T
+    data: T,
+    peer_addr: SocketAddr,
+    timeouts: Timeouts,
+    read_delay: Option<Duration>,
+    write_delay: Option<Duration>,
 }
 
 impl<T> MockStream<T> {
     /// Create a new mock stream that reads from the given data
     pub fn new(data: T) -> MockStream<T> {
-        MockStream { data: data }
+        MockStream::with_peer_addr(data, "127.0.0.1:3000".parse().unwrap())
+    }
+
+    /// Create a new mock stream that reads from the given data, connected to
+    /// a peer at the given address
+    pub fn with_peer_addr(data: T, peer_addr: SocketAddr) -> MockStream<T> {
+        MockStream {
+            data: data,
+            peer_addr: peer_addr,
+            timeouts: Timeouts::new(),
+            read_delay: None,
+            write_delay: None,
+        }
+    }
+
+    /// Simulate a connection on which each read waits for the given delay.
+    /// Reads fail with `WouldBlock`, as they do on Unix, while the read
+    /// timeout is shorter than the delay; no time actually passes.
+    pub fn read_delay(mut self, delay: Duration) -> MockStream<T> {
+        self.read_delay = Some(delay);
+        self
+    }
+
+    /// Simulate a connection on which each write waits for the given delay.
+    /// Writes fail with `WouldBlock` while the write timeout is shorter than
+    /// the delay.
+    pub fn write_delay(mut self, delay: Duration) -> MockStream<T> {
+        self.write_delay = Some(delay);
+        self
+    }
+
+    /// A handle to the timeouts set on the stream, which can be kept to
+    /// inspect them after the stream is given away
+    pub fn timeouts(&self) -> Timeouts {
+        self.timeouts.clone()
+    }
+
+    /// Get a reference to the data of the stream
+    pub fn get_ref(&self) -> &T {
+        &self.data
+    }
+
+    /// Get a mutable reference to the data of the stream
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Consume the stream, returning its data
+    pub fn into_inner(self) -> T {
+        self.data
     }
 }
 
-impl<T: Send + Read + Write + Clone + Any> NetworkStream for MockStream<T> {
-    fn peer_addr(&mut self) -> Result<SocketAddr> {
-        Ok("127.0.0.1:3000".parse().unwrap())
+impl MockStream<Duplex> {
+    /// Create a new mock stream that reads from the given input, and writes
+    /// to a separate output
+    pub fn duplex<B: Into<Vec<u8>>>(input: B) -> MockStream<Duplex> {
+        MockStream::new(Duplex::new(input))
+    }
+
+    /// A handle to the output of the stream, which can be kept to inspect
+    /// what was written after the stream is given away
+    pub fn output(&self) -> Output {
+        self.data.output.clone()
     }
 
-    fn set_read_timeout(&self, _: Option<Duration>) -> Result<()> {
+    /// The bytes written to the stream so far
+    pub fn written(&self) -> Vec<u8> {
+        self.data.output.bytes()
+    }
+}
+
+/// The data of a duplex mock stream: reads come from the input, and writes
+/// go to a separate output, like a network connection
+///
+/// Clones share the same output.
+#[derive(Clone, Debug)]
+pub struct Duplex {
+    input: Cursor<Vec<u8>>,
+    output: Output,
+}
+
+impl Duplex {
+    /// Create a duplex which reads from the given input, with an empty output
+    pub fn new<B: Into<Vec<u8>>>(input: B) -> Duplex {
+        Duplex { input: Cursor::new(input.into()), output: Output::new() }
+    }
+
+    /// The input of the duplex, and how much of it has been read
+    pub fn input(&self) -> &Cursor<Vec<u8>> {
+        &self.input
+    }
+
+    /// The output of the duplex
+    pub fn output(&self) -> &Output {
+        &self.output
+    }
+}
+
+impl Read for Duplex {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for Duplex {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
         Ok(())
     }
+}
+
+/// A shared buffer which collects the bytes written to a duplex mock stream
+#[derive(Clone, Debug, Default)]
+pub struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Output {
+    /// Create an empty output
+    pub fn new() -> Output {
+        Output::default()
+    }
 
-    fn set_write_timeout(&self, _: Option<Duration>) -> Result<()> {
+    /// The bytes written so far
+    pub fn bytes(&self) -> Vec<u8> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Discard the bytes written so far
+    pub fn clear(&self) {
+        self.0.lock().unwrap().clear()
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A call to `set_read_timeout` or `set_write_timeout` on a mock stream
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeoutCall {
+    /// The read timeout was set to the given value
+    Read(Option<Duration>),
+    /// The write timeout was set to the given value
+    Write(Option<Duration>),
+}
+
+/// A shared record of the timeouts set on a mock stream
+///
+/// Clones share the same record.
+#[derive(Clone, Debug, Default)]
+pub struct Timeouts(Arc<Mutex<TimeoutRecord>>);
+
+#[derive(Debug, Default)]
+struct TimeoutRecord {
+    calls: Vec<TimeoutCall>,
+    read: Option<Duration>,
+    write: Option<Duration>,
+}
+
+impl Timeouts {
+    /// Create an empty record
+    pub fn new() -> Timeouts {
+        Timeouts::default()
+    }
+
+    /// Every call made to set a timeout, in order, including those which
+    /// failed
+    pub fn calls(&self) -> Vec<TimeoutCall> {
+        self.0.lock().unwrap().calls.clone()
+    }
+
+    /// The current read timeout
+    pub fn read(&self) -> Option<Duration> {
+        self.0.lock().unwrap().read
+    }
+
+    /// The current write timeout
+    pub fn write(&self) -> Option<Duration> {
+        self.0.lock().unwrap().write
+    }
+
+    fn record(&self, call: TimeoutCall) -> Result<()> {
+        let mut record = self.0.lock().unwrap();
+        record.calls.push(call);
+        match call {
+            // As with a TcpStream, a zero timeout is rejected.
+            TimeoutCall::Read(Some(timeout)) | TimeoutCall::Write(Some(timeout)) if timeout == Duration::new(0, 0) => {
+                return Err(Error::new(ErrorKind::InvalidInput, "cannot set a 0 duration timeout"));
+            },
+            TimeoutCall::Read(timeout) => record.read = timeout,
+            TimeoutCall::Write(timeout) => record.write = timeout,
+        }
         Ok(())
     }
 }
 
+// Whether an operation which waits for the given delay runs past the timeout.
+fn timed_out(delay: Option<Duration>, timeout: Option<Duration>) -> bool {
+    match (delay, timeout) {
+        (Some(delay), Some(timeout)) => delay > timeout,
+        _ => false,
+    }
+}
+
+impl<T: Send + Read + Write + Any> NetworkStream for MockStream<T> {
+    fn peer_addr(&mut self) -> Result<SocketAddr> {
+        Ok(self.peer_addr)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        self.timeouts.record(TimeoutCall::Read(timeout))
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        self.timeouts.record(TimeoutCall::Write(timeout))
+    }
+}
+
 impl<T: Read> Read for MockStream<T> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if timed_out(self.read_delay, self.timeouts.read()) {
+            return Err(Error::new(ErrorKind::WouldBlock, "read timed out"));
+        }
         self.data.read(buf)
     }
 }
 
 impl<T: Write> Write for MockStream<T> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if timed_out(self.write_delay, self.timeouts.write()) {
+            return Err(Error::new(ErrorKind::WouldBlock, "write timed out"));
+        }
+        self.data.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.data.flush()
+    }
+}
+
+/// Faults to inject into the data read from a stream, by wrapping it in a
+/// `Faulty`
+///
+/// Offsets are counted in bytes from the start of the data.
+#[derive(Clone, Debug, Default)]
+pub struct Faults {
+    fragments: Fragments,
+    error: Option<(u64, ErrorKind)>,
+    eof: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+enum Fragments {
+    Whole,
+    Fixed(usize),
+    Random(usize, u64),
+}
+
+impl Default for Fragments {
+    fn default() -> Fragments {
+        Fragments::Whole
+    }
+}
+
+impl Faults {
+    /// No faults: every read returns as much data as fits
+    pub fn new() -> Faults {
+        Faults::default()
+    }
+
+    /// Return at most `size` bytes from each read
+    pub fn fragments(mut self, size: usize) -> Faults {
+        assert!(size > 0, "fragment size must be greater than zero");
+        self.fragments = Fragments::Fixed(size);
+        self
+    }
+
+    /// Return between 1 and `max` bytes from each read, chosen by a random
+    /// number generator with the given seed so that failures can be replayed
+    pub fn random_fragments(mut self, max: usize, seed: u64) -> Faults {
+        assert!(max > 0, "fragment size must be greater than zero");
+        // The generator gets stuck at zero.
+        self.fragments = Fragments::Random(max, if seed == 0 { 1 } else { seed });
+        self
+    }
+
+    /// Return an error of the given kind from the read at `offset`
+    ///
+    /// `WouldBlock` and `Interrupted` errors are returned once, after which
+    /// reading can continue; any other error is returned by every later read.
+    pub fn error_at(mut self, offset: u64, kind: ErrorKind) -> Faults {
+        self.error = Some((offset, kind));
+        self
+    }
+
+    /// End the data at `offset`, as if the connection was closed
+    pub fn eof_at(mut self, offset: u64) -> Faults {
+        self.eof = Some(offset);
+        self
+    }
+}
+
+/// Data for a mock stream which injects faults into what is read from the
+/// data it wraps, such as fragmented reads, errors and early EOF
+///
+/// Writes are passed through unchanged.
+#[derive(Clone, Debug)]
+pub struct Faulty<T> {
+    data: T,
+    faults: Faults,
+    position: u64,
+    error_returned: bool,
+}
+
+impl<T> Faulty<T> {
+    /// Wrap the given data, injecting the given faults
+    pub fn new(data: T, faults: Faults) -> Faulty<T> {
+        Faulty { data: data, faults: faults, position: 0, error_returned: false }
+    }
+
+    /// The number of bytes read so far
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Consume the wrapper, returning the data
+    pub fn into_inner(self) -> T {
+        self.data
+    }
+
+    fn fragment_size(&mut self) -> usize {
+        match self.faults.fragments {
+            Fragments::Whole => usize::max_value(),
+            Fragments::Fixed(size) => size,
+            Fragments::Random(max, ref mut state) => {
+                // xorshift64
+                *state ^= *state << 13;
+                *state ^= *state >> 7;
+                *state ^= *state << 17;
+                (*state % max as u64) as usize + 1
+            },
+        }
+    }
+}
+
+impl<T: Read> Read for Faulty<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let mut limit = buf.len() as u64;
+
+        if let Some((offset, kind)) = self.faults.error {
+            let retryable = kind == ErrorKind::WouldBlock || kind == ErrorKind::Interrupted;
+            if self.position >= offset && !(retryable && self.error_returned) {
+                self.error_returned = true;
+                return Err(Error::new(kind, format!("injected error at byte {}", offset)));
+            }
+            if self.position < offset {
+                limit = limit.min(offset - self.position);
+            }
+        }
+        if let Some(eof) = self.faults.eof {
+            limit = limit.min(eof.saturating_sub(self.position));
+        }
+        limit = limit.min(self.fragment_size() as u64);
+
+        if limit == 0 {
+            return Ok(0);
+        }
+        let n = try!(self.data.read(&mut buf[..limit as usize]));
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl<T: Write> Write for Faulty<T> {
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
         self.data.write(buf)
     }
@@ -48,3 +412,174 @@ impl<T: Write> Write for MockStream<T> {
         self.data.flush()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use hyper::buffer::BufReader;
+    use hyper::header::Headers;
+    use hyper::net::NetworkStream;
+    use hyper::server::{Request, Response};
+
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_peer_addr() {
+        let mut stream = MockStream::new(Cursor::new(vec![]));
+        assert_eq!(stream.peer_addr().unwrap(), "127.0.0.1:3000".parse().unwrap());
+
+        let addr = "[2001:db8::1]:8080".parse().unwrap();
+        let mut stream = MockStream::with_peer_addr(Cursor::new(vec![]), addr);
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+    }
+
+    #[test]
+    fn test_duplex() {
+        let mut stream = MockStream::duplex("request");
+        stream.write_all(b"response").unwrap();
+
+        let mut input = String::new();
+        stream.read_to_string(&mut input).unwrap();
+        assert_eq!(input, "request");
+        assert_eq!(stream.written(), b"response");
+
+        let output = stream.output();
+        stream.clone().write_all(b" and more").unwrap();
+        assert_eq!(output.bytes(), b"response and more");
+
+        output.clear();
+        assert_eq!(stream.written(), b"");
+    }
+
+    #[test]
+    fn test_duplex_with_hyper() {
+        let mut stream = MockStream::duplex("GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        let output = stream.output();
+
+        {
+            let addr = "127.0.0.1:3000".parse().unwrap();
+            let mut reader = BufReader::new(&mut stream as &mut NetworkStream);
+            let request = Request::new(&mut reader, addr).unwrap();
+            assert_eq!(request.uri.to_string(), "/hello");
+        }
+
+        let mut headers = Headers::new();
+        Response::new(&mut stream, &mut headers).send(b"Hello, world!").unwrap();
+
+        let written = String::from_utf8(output.bytes()).unwrap();
+        assert!(written.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(written.ends_with("\r\n\r\nHello, world!"));
+        assert_eq!(stream.get_ref().input().position(), 40);
+    }
+
+    #[test]
+    fn test_timeouts() {
+        let stream = MockStream::new(Cursor::new(vec![]));
+        let timeouts = stream.timeouts();
+        assert_eq!(timeouts.read(), None);
+
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        stream.set_write_timeout(Some(Duration::from_secs(1))).unwrap();
+        assert!(stream.set_read_timeout(Some(Duration::from_secs(0))).is_err());
+        stream.set_write_timeout(None).unwrap();
+
+        assert_eq!(timeouts.calls(), vec![
+            TimeoutCall::Read(Some(Duration::from_secs(5))),
+            TimeoutCall::Write(Some(Duration::from_secs(1))),
+            TimeoutCall::Read(Some(Duration::from_secs(0))),
+            TimeoutCall::Write(None),
+        ]);
+        assert_eq!(timeouts.read(), Some(Duration::from_secs(5)));
+        assert_eq!(timeouts.write(), None);
+    }
+
+    #[test]
+    fn test_delays() {
+        let mut stream = MockStream::duplex("request")
+            .read_delay(Duration::from_secs(10))
+            .write_delay(Duration::from_secs(10));
+
+        // Without timeouts, the stream waits as long as it takes.
+        assert_eq!(stream.read(&mut [0; 3]).unwrap(), 3);
+        stream.write_all(b"ok").unwrap();
+
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        stream.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(stream.read(&mut [0; 3]).unwrap_err().kind(), ErrorKind::WouldBlock);
+        assert_eq!(stream.write(b"ok").unwrap_err().kind(), ErrorKind::WouldBlock);
+
+        stream.set_read_timeout(Some(Duration::from_secs(30))).unwrap();
+        assert_eq!(stream.read(&mut [0; 3]).unwrap(), 3);
+        assert_eq!(stream.written(), b"ok");
+    }
+
+    #[test]
+    fn test_delays_with_hyper() {
+        let mut stream = MockStream::duplex("GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .read_delay(Duration::from_secs(10));
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        let addr = "127.0.0.1:3000".parse().unwrap();
+        let mut reader = BufReader::new(&mut stream as &mut NetworkStream);
+        match Request::new(&mut reader, addr) {
+            Err(::hyper::Error::Io(err)) => assert_eq!(err.kind(), ErrorKind::WouldBlock),
+            _ => panic!("expected the read to time out"),
+        }
+    }
+
+    fn read_in_pieces<R: Read>(mut reader: R) -> (Vec<usize>, Result<()>) {
+        let mut sizes = vec![];
+        let mut buf = [0; 64];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) => return (sizes, Ok(())),
+                Ok(n) => sizes.push(n),
+                Err(err) => return (sizes, Err(err)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_fragments() {
+        let data = Cursor::new(vec![0; 10]);
+        let (sizes, result) = read_in_pieces(Faulty::new(data, Faults::new().fragments(3)));
+        assert_eq!(sizes, vec![3, 3, 3, 1]);
+        assert!(result.is_ok());
+
+        let faults = Faults::new().random_fragments(4, 42);
+        let (sizes, _) = read_in_pieces(Faulty::new(Cursor::new(vec![0; 100]), faults.clone()));
+        assert!(sizes.iter().all(|&size| size >= 1 && size <= 4));
+        assert_eq!(sizes.iter().sum::<usize>(), 100);
+
+        let (replayed, _) = read_in_pieces(Faulty::new(Cursor::new(vec![0; 100]), faults));
+        assert_eq!(sizes, replayed);
+    }
+
+    #[test]
+    fn test_errors() {
+        let faults = Faults::new().error_at(5, ErrorKind::WouldBlock);
+        let mut data = Faulty::new(Cursor::new(vec![0; 10]), faults);
+        let (sizes, result) = read_in_pieces(&mut data);
+        assert_eq!(sizes, vec![5]);
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::WouldBlock);
+        let (sizes, result) = read_in_pieces(&mut data);
+        assert_eq!(sizes, vec![5]);
+        assert!(result.is_ok());
+        assert_eq!(data.position(), 10);
+
+        let faults = Faults::new().error_at(0, ErrorKind::ConnectionReset);
+        let mut data = Faulty::new(Cursor::new(vec![0; 10]), faults);
+        for _ in 0..2 {
+            assert_eq!(data.read(&mut [0; 10]).unwrap_err().kind(), ErrorKind::ConnectionReset);
+        }
+    }
+
+    #[test]
+    fn test_eof() {
+        let faults = Faults::new().eof_at(4);
+        let (sizes, result) = read_in_pieces(Faulty::new(Cursor::new(vec![0; 10]), faults));
+        assert_eq!(sizes, vec![4]);
+        assert!(result.is_ok());
+    }
+}
@@ -0,0 +1,172 @@
+use iron::mime::{Attr, Mime, SubLevel, TopLevel, Value};
+
+use uuid::Uuid;
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// A builder for `multipart/form-data` request bodies.
+///
+/// Text fields and file parts are written in the order they are added,
+/// separated by a boundary which is generated when the builder is created.
+/// Pass the builder to `RequestBuilder::multipart` to set it as the body of a
+/// request, along with the matching Content-Type header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Multipart {
+    boundary: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Part {
+    name: String,
+    filename: Option<String>,
+    content_type: Option<Mime>,
+    body: Vec<u8>,
+}
+
+impl Multipart {
+    /// Create an empty multipart body with a random boundary.
+    pub fn new() -> Multipart {
+        Multipart::with_boundary(&format!("iron-test-{}", Uuid::new_v4().simple()))
+    }
+
+    /// Create an empty multipart body with the given boundary.
+    pub fn with_boundary(boundary: &str) -> Multipart {
+        Multipart { boundary: boundary.to_owned(), parts: vec![] }
+    }
+
+    /// The boundary between the parts of the body.
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// Add a text field.
+    pub fn text(mut self, name: &str, value: &str) -> Multipart {
+        self.parts.push(Part {
+            name: name.to_owned(),
+            filename: None,
+            content_type: None,
+            body: value.as_bytes().to_vec(),
+        });
+        self
+    }
+
+    /// Add a file part with the given filename, Content-Type and contents.
+    pub fn file<B: Into<Vec<u8>>>(mut self, name: &str, filename: &str, content_type: Mime, body: B) -> Multipart {
+        self.parts.push(Part {
+            name: name.to_owned(),
+            filename: Some(filename.to_owned()),
+            content_type: Some(content_type),
+            body: body.into(),
+        });
+        self
+    }
+
+    /// Add a file part with the contents of the file at the given path, for
+    /// example a file in the root of a `ProjectBuilder`. The filename of the
+    /// part is the last component of the path.
+    pub fn file_path<P: AsRef<Path>>(self, name: &str, path: P, content_type: Mime) -> io::Result<Multipart> {
+        let path = path.as_ref();
+        let filename = path.file_name()
+            .map(|filename| filename.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let mut body = Vec::new();
+        try!(try!(File::open(path)).read_to_end(&mut body));
+
+        Ok(self.file(name, &filename, content_type, body))
+    }
+
+    /// The Content-Type of the body, `multipart/form-data` with the boundary.
+    pub fn content_type(&self) -> Mime {
+        Mime(TopLevel::Multipart, SubLevel::FormData,
+             vec![(Attr::Boundary, Value::Ext(self.boundary.clone()))])
+    }
+
+    /// Write the parts of the body, separated by the boundary.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        for part in self.parts.iter() {
+            bytes.extend(format!("--{}\r\n", self.boundary).into_bytes());
+            bytes.extend(format!("Content-Disposition: form-data; name=\"{}\"", quote(&part.name)).into_bytes());
+            if let Some(ref filename) = part.filename {
+                bytes.extend(format!("; filename=\"{}\"", quote(filename)).into_bytes());
+            }
+            bytes.extend(b"\r\n");
+            if let Some(ref content_type) = part.content_type {
+                bytes.extend(format!("Content-Type: {}\r\n", content_type).into_bytes());
+            }
+            bytes.extend(b"\r\n");
+            bytes.extend(part.body.iter());
+            bytes.extend(b"\r\n");
+        }
+        bytes.extend(format!("--{}--\r\n", self.boundary).into_bytes());
+
+        bytes
+    }
+}
+
+impl Default for Multipart {
+    fn default() -> Multipart {
+        Multipart::new()
+    }
+}
+
+// Escape a name or filename for a quoted-string in Content-Disposition.
+fn quote(value: &str) -> String {
+    value.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+#[cfg(test)]
+mod test {
+    use iron::mime::Mime;
+
+    use ProjectBuilder;
+
+    use super::*;
+
+    #[test]
+    fn test_to_bytes() {
+        let png: Mime = "image/png".parse().unwrap();
+        let multipart = Multipart::with_boundary("xyz")
+            .text("title", "Holiday")
+            .file("photo", "beach \"1\".png", png, vec![0x89, b'P', b'N', b'G']);
+
+        assert_eq!(multipart.content_type().to_string(), "multipart/form-data; boundary=xyz");
+        assert_eq!(multipart.to_bytes(),
+                   &b"--xyz\r\n\
+                      Content-Disposition: form-data; name=\"title\"\r\n\
+                      \r\n\
+                      Holiday\r\n\
+                      --xyz\r\n\
+                      Content-Disposition: form-data; name=\"photo\"; filename=\"beach \\\"1\\\".png\"\r\n\
+                      Content-Type: image/png\r\n\
+                      \r\n\
+                      \x89PNG\r\n\
+                      --xyz--\r\n"[..]);
+    }
+
+    #[test]
+    fn test_file_path() {
+        let p = ProjectBuilder::new("multipart").file("notes.txt", "some notes");
+        p.build();
+
+        let text: Mime = "text/plain".parse().unwrap();
+        let multipart = Multipart::with_boundary("xyz")
+            .file_path("notes", p.root().join("notes.txt"), text.clone())
+            .unwrap();
+
+        assert_eq!(multipart, Multipart::with_boundary("xyz").file("notes", "notes.txt", text, "some notes"));
+    }
+
+    #[test]
+    fn test_random_boundary() {
+        assert!(Multipart::new().boundary() != Multipart::new().boundary());
+    }
+}
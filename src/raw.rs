@@ -0,0 +1,205 @@
+use hyper;
+use iron;
+use iron::prelude::*;
+use iron::Handler;
+
+use std::io::Cursor;
+use std::net::SocketAddr;
+
+use super::mock_stream::{Faults, Faulty};
+use super::request::dispatch;
+
+/// A request made from raw bytes, which are passed to hyper's parser as they
+/// are.
+///
+/// Unlike `RequestBuilder`, nothing is added to the request: no Content-Length,
+/// User-Agent or Host header is written for it, so malformed and unusual
+/// requests can be sent to a Handler.
+#[derive(Debug, Clone)]
+pub struct RawRequest {
+    bytes: Vec<u8>,
+    remote_addr: SocketAddr,
+    faults: Faults,
+}
+
+impl RawRequest {
+    /// Create a request from the given bytes, including the request line,
+    /// headers and body.
+    pub fn new<B: Into<Vec<u8>>>(bytes: B) -> RawRequest {
+        RawRequest {
+            bytes: bytes.into(),
+            remote_addr: "127.0.0.1:3000".parse().unwrap(),
+            faults: Faults::new(),
+        }
+    }
+
+    /// Set the address of the client making the request.
+    pub fn remote_addr(mut self, addr: SocketAddr) -> RawRequest {
+        self.remote_addr = addr;
+        self
+    }
+
+    /// Inject the given faults into the request as it is read. Offsets are
+    /// counted from the start of the request line.
+    pub fn faults(mut self, faults: Faults) -> RawRequest {
+        self.faults = faults;
+        self
+    }
+
+    /// The bytes of the request.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Parse the request and, if it is valid, send it to the given Handler.
+    pub fn send<H: Handler + ?Sized>(&self, handler: &H) -> RawOutcome {
+        let data = Faulty::new(Cursor::new(self.bytes.clone()), self.faults.clone());
+        dispatch(data, self.remote_addr, &iron::Protocol::http(), &[], handler)
+    }
+}
+
+/// The outcome of sending a `RawRequest` to a Handler.
+#[derive(Debug)]
+pub enum RawOutcome {
+    /// hyper failed to parse the request, so the Handler was not called.
+    ParseFailed(hyper::Error),
+    /// hyper parsed the request, but Iron failed to construct a Request from
+    /// it, for example because it has no Host header. The Handler was not
+    /// called.
+    Rejected(String),
+    /// The Handler was called, and returned the given result.
+    Handled(IronResult<Response>),
+}
+
+impl RawOutcome {
+    /// Whether the Handler was called.
+    pub fn is_handled(&self) -> bool {
+        match *self {
+            RawOutcome::Handled(_) => true,
+            _ => false,
+        }
+    }
+
+    /// The error returned by hyper, if it failed to parse the request.
+    pub fn parse_error(&self) -> Option<&hyper::Error> {
+        match *self {
+            RawOutcome::ParseFailed(ref err) => Some(err),
+            _ => None,
+        }
+    }
+
+    /// The result returned by the Handler.
+    ///
+    /// Panics if the Handler was not called.
+    pub fn unwrap_handled(self) -> IronResult<Response> {
+        match self {
+            RawOutcome::Handled(result) => result,
+            RawOutcome::ParseFailed(err) => panic!("expected the request to be handled, but it failed to parse: {}", err),
+            RawOutcome::Rejected(reason) => panic!("expected the request to be handled, but it was rejected: {}", reason),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use iron::prelude::*;
+    use iron::{Handler, status};
+
+    use std::io::{ErrorKind, Read};
+
+    use response::extract_body_to_string;
+
+    use super::*;
+
+    struct EchoHandler;
+
+    impl Handler for EchoHandler {
+        fn handle(&self, req: &mut Request) -> IronResult<Response> {
+            let mut body = String::new();
+            req.body.read_to_string(&mut body).unwrap();
+            Ok(Response::with((status::Ok, format!("{} {} {}", req.method, req.url, body))))
+        }
+    }
+
+    fn handled(bytes: &[u8]) -> String {
+        extract_body_to_string(RawRequest::new(bytes).send(&EchoHandler).unwrap_handled().unwrap())
+    }
+
+    #[test]
+    fn test_handled() {
+        assert_eq!(handled(b"POST /users HTTP/1.1\r\nHost: localhost\r\nContent-Length: 4\r\n\r\nnameignored"),
+                   "POST http://localhost/users name");
+        assert_eq!(handled(b"get / HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+                   "get http://localhost/ ");
+        assert_eq!(handled(b"GET /lf HTTP/1.1\nHost: localhost\n\n"),
+                   "GET http://localhost/lf ");
+        assert_eq!(handled(b"PUT / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 2\r\nContent-Length: 2\r\n\r\nab"),
+                   "PUT http://localhost/ ab");
+    }
+
+    #[test]
+    fn test_parse_failed() {
+        let outcome = RawRequest::new(&b"GET\r\n\r\n"[..]).send(&EchoHandler);
+        assert!(!outcome.is_handled());
+        assert!(outcome.parse_error().is_some());
+
+        let outcome = RawRequest::new(&b"GET / HTTP/1.1\r\nHost localhost\r\n\r\n"[..]).send(&EchoHandler);
+        assert!(outcome.parse_error().is_some());
+    }
+
+    #[test]
+    fn test_bad_content_length() {
+        let conflicting = &b"PUT / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 2\r\nContent-Length: 3\r\n\r\nabc"[..];
+        match RawRequest::new(conflicting).send(&EchoHandler) {
+            RawOutcome::ParseFailed(hyper::Error::Header) => (),
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+
+        let non_numeric = &b"PUT / HTTP/1.1\r\nHost: localhost\r\nContent-Length: two\r\n\r\nab"[..];
+        match RawRequest::new(non_numeric).send(&EchoHandler) {
+            RawOutcome::ParseFailed(hyper::Error::Header) => (),
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn test_rejected() {
+        match RawRequest::new(&b"GET / HTTP/1.1\r\n\r\n"[..]).send(&EchoHandler) {
+            RawOutcome::Rejected(reason) => assert_eq!(reason, "No host specified in request"),
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn test_remote_addr() {
+        struct RemoteAddrHandler;
+
+        impl Handler for RemoteAddrHandler {
+            fn handle(&self, req: &mut Request) -> IronResult<Response> {
+                Ok(Response::with((status::Ok, req.remote_addr.to_string())))
+            }
+        }
+
+        let response = RawRequest::new(&b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n"[..])
+            .remote_addr("10.0.0.1:4321".parse().unwrap())
+            .send(&RemoteAddrHandler)
+            .unwrap_handled()
+            .unwrap();
+        assert_eq!(extract_body_to_string(response), "10.0.0.1:4321");
+    }
+
+    #[test]
+    fn test_faults() {
+        let bytes = &b"POST /users HTTP/1.1\r\nHost: localhost\r\nContent-Length: 4\r\n\r\nname"[..];
+
+        let outcome = RawRequest::new(bytes).faults(Faults::new().fragments(1)).send(&EchoHandler);
+        assert_eq!(extract_body_to_string(outcome.unwrap_handled().unwrap()), "POST http://localhost/users name");
+
+        let outcome = RawRequest::new(bytes).faults(Faults::new().eof_at(20)).send(&EchoHandler);
+        assert!(outcome.parse_error().is_some());
+
+        let faults = Faults::new().error_at(0, ErrorKind::ConnectionReset);
+        let outcome = RawRequest::new(bytes).faults(faults).send(&EchoHandler);
+        assert!(outcome.parse_error().is_some());
+    }
+}
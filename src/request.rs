@@ -4,12 +4,30 @@ use hyper::buffer::BufReader;
 use hyper::net::NetworkStream;
 
 use iron;
+use iron::headers::{Header, HeaderFormat};
 use iron::prelude::*;
+use iron::typemap::{Key, TypeMap};
 use iron::{Handler, headers, Headers, method, Url};
 
-use std::io::Cursor;
+use url;
 
-use super::mock_stream::MockStream;
+#[cfg(feature = "json")]
+use serde::Serialize;
+#[cfg(feature = "json")]
+use serde_json;
+
+use std::fmt::Write as FmtWrite;
+use std::io::{self, Cursor, Read, Write};
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+
+use super::error::TestError;
+use super::form::Form;
+use super::mock_stream::{Faults, Faulty, MockStream};
+use super::multipart::Multipart;
+use super::raw::RawOutcome;
+use super::request_body::RequestBody;
 
 /// Convenience method for making GET requests to Iron Handlers.
 pub fn get<H: Handler>(path: &str, headers: Headers, handler: &H) -> IronResult<Response> {
@@ -46,42 +64,533 @@ pub fn head<H: Handler>(path: &str, headers: Headers, handler: &H) -> IronResult
     request(method::Head, path, "", headers, handler)
 }
 
+/// Convenience method for making TRACE requests to Iron Handlers.
+pub fn trace<H: Handler>(path: &str, headers: Headers, handler: &H) -> IronResult<Response> {
+    request(method::Trace, path, "", headers, handler)
+}
+
+/// Convenience method for making CONNECT requests to Iron Handlers.
+pub fn connect<H: Handler>(path: &str, headers: Headers, handler: &H) -> IronResult<Response> {
+    request(method::Connect, path, "", headers, handler)
+}
+
 /// Constructs an Iron::Request from the given parts and passes it to the
 /// `handle` method on the given Handler.
+///
+/// Any method can be used, including extension methods such as
+/// `"PROPFIND".parse().unwrap()`.
 pub fn request<H: Handler>(method: method::Method,
                            path: &str,
                            body: &str,
                            headers: Headers,
                            handler: &H) -> IronResult<Response> {
-    let url = Url::parse(path).unwrap();
+    RequestBuilder::new(method, path)
+        .headers(headers)
+        .body(body)
+        .send(handler)
+}
+
+/// Convenience method for making GET requests to Iron Handlers, returning an
+/// error rather than panicking if the request cannot be constructed.
+pub fn try_get<H: Handler>(path: &str, headers: Headers, handler: &H) -> Result<Response, TestError> {
+    try_request(method::Get, path, "", headers, handler)
+}
+
+/// Convenience method for making POST requests with a body to Iron Handlers,
+/// returning an error rather than panicking if the request cannot be constructed.
+pub fn try_post<H: Handler>(path: &str, headers: Headers, body: &str, handler: &H) -> Result<Response, TestError> {
+    try_request(method::Post, path, body, headers, handler)
+}
+
+/// Convenience method for making PATCH requests with a body to Iron Handlers,
+/// returning an error rather than panicking if the request cannot be constructed.
+pub fn try_patch<H: Handler>(path: &str, headers: Headers, body: &str, handler: &H) -> Result<Response, TestError> {
+    try_request(method::Patch, path, body, headers, handler)
+}
+
+/// Convenience method for making PUT requests with a body to Iron Handlers,
+/// returning an error rather than panicking if the request cannot be constructed.
+pub fn try_put<H: Handler>(path: &str, headers: Headers, body: &str, handler: &H) -> Result<Response, TestError> {
+    try_request(method::Put, path, body, headers, handler)
+}
+
+/// Convenience method for making DELETE requests to Iron Handlers, returning
+/// an error rather than panicking if the request cannot be constructed.
+pub fn try_delete<H: Handler>(path: &str, headers: Headers, handler: &H) -> Result<Response, TestError> {
+    try_request(method::Delete, path, "", headers, handler)
+}
+
+/// Convenience method for making OPTIONS requests to Iron Handlers, returning
+/// an error rather than panicking if the request cannot be constructed.
+pub fn try_options<H: Handler>(path: &str, headers: Headers, handler: &H) -> Result<Response, TestError> {
+    try_request(method::Options, path, "", headers, handler)
+}
+
+/// Convenience method for making HEAD requests to Iron Handlers, returning an
+/// error rather than panicking if the request cannot be constructed.
+pub fn try_head<H: Handler>(path: &str, headers: Headers, handler: &H) -> Result<Response, TestError> {
+    try_request(method::Head, path, "", headers, handler)
+}
+
+/// Convenience method for making TRACE requests to Iron Handlers, returning
+/// an error rather than panicking if the request cannot be constructed.
+pub fn try_trace<H: Handler>(path: &str, headers: Headers, handler: &H) -> Result<Response, TestError> {
+    try_request(method::Trace, path, "", headers, handler)
+}
+
+/// Convenience method for making CONNECT requests to Iron Handlers, returning
+/// an error rather than panicking if the request cannot be constructed.
+pub fn try_connect<H: Handler>(path: &str, headers: Headers, handler: &H) -> Result<Response, TestError> {
+    try_request(method::Connect, path, "", headers, handler)
+}
+
+/// Constructs an Iron::Request from the given parts and passes it to the
+/// `handle` method on the given Handler, returning an error rather than
+/// panicking if the request cannot be constructed.
+pub fn try_request<H: Handler>(method: method::Method,
+                               path: &str,
+                               body: &str,
+                               headers: Headers,
+                               handler: &H) -> Result<Response, TestError> {
+    RequestBuilder::new(method, path)
+        .headers(headers)
+        .body(body)
+        .try_send(handler)
+}
+
+/// A builder for requests to Iron Handlers.
+///
+/// The method, URL, headers, body and extensions of the request can be set
+/// incrementally. A builder can be cloned to make variants of a request, and
+/// can be sent any number of times, unless its body streams from a reader,
+/// which can only be sent once; see `RequestBody`.
+#[derive(Clone)]
+pub struct RequestBuilder {
+    method: method::Method,
+    url: String,
+    query: Vec<(String, String)>,
+    headers: Headers,
+    body: RequestBody,
+    remote_addr: SocketAddr,
+    chunk_size: Option<usize>,
+    faults: Faults,
+    extensions: Vec<Arc<Fn(&mut TypeMap) + Send + Sync>>,
+}
+
+impl RequestBuilder {
+    /// Create a new builder for a request with the given method and URL.
+    pub fn new(method: method::Method, url: &str) -> RequestBuilder {
+        RequestBuilder {
+            method: method,
+            url: url.to_owned(),
+            query: vec![],
+            headers: Headers::new(),
+            body: RequestBody::empty(),
+            remote_addr: "127.0.0.1:3000".parse().unwrap(),
+            chunk_size: None,
+            faults: Faults::new(),
+            extensions: vec![],
+        }
+    }
+
+    /// Create a new builder for a GET request.
+    pub fn get(url: &str) -> RequestBuilder {
+        RequestBuilder::new(method::Get, url)
+    }
+
+    /// Create a new builder for a POST request.
+    pub fn post(url: &str) -> RequestBuilder {
+        RequestBuilder::new(method::Post, url)
+    }
+
+    /// Create a new builder for a PATCH request.
+    pub fn patch(url: &str) -> RequestBuilder {
+        RequestBuilder::new(method::Patch, url)
+    }
+
+    /// Create a new builder for a PUT request.
+    pub fn put(url: &str) -> RequestBuilder {
+        RequestBuilder::new(method::Put, url)
+    }
+
+    /// Create a new builder for a DELETE request.
+    pub fn delete(url: &str) -> RequestBuilder {
+        RequestBuilder::new(method::Delete, url)
+    }
+
+    /// Create a new builder for an OPTIONS request.
+    pub fn options(url: &str) -> RequestBuilder {
+        RequestBuilder::new(method::Options, url)
+    }
+
+    /// Create a new builder for a HEAD request.
+    pub fn head(url: &str) -> RequestBuilder {
+        RequestBuilder::new(method::Head, url)
+    }
+
+    /// Create a new builder for a TRACE request.
+    pub fn trace(url: &str) -> RequestBuilder {
+        RequestBuilder::new(method::Trace, url)
+    }
+
+    /// Create a new builder for a CONNECT request.
+    ///
+    /// Iron only accepts an absolute URL as the target of a request, so the
+    /// request is sent with the URL rather than just its host and port.
+    pub fn connect(url: &str) -> RequestBuilder {
+        RequestBuilder::new(method::Connect, url)
+    }
+
+    /// Create a new builder for a request with the method of the given name,
+    /// such as `PROPFIND`, `MKCOL` or `PURGE`. Method names are case-sensitive.
+    pub fn custom(method: &str, url: &str) -> RequestBuilder {
+        let method = method.parse().unwrap_or_else(|_| method::Extension(method.to_owned()));
+        RequestBuilder::new(method, url)
+    }
+
+    /// Set the method of the request.
+    pub fn method(mut self, method: method::Method) -> RequestBuilder {
+        self.method = method;
+        self
+    }
+
+    /// Set the URL of the request.
+    pub fn url(mut self, url: &str) -> RequestBuilder {
+        self.url = url.to_owned();
+        self
+    }
+
+    /// Append a key/value pair to the query string of the URL.
+    pub fn query(mut self, key: &str, value: &str) -> RequestBuilder {
+        self.query.push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Set a header on the request, replacing any previous value.
+    pub fn header<H: Header + HeaderFormat>(mut self, header: H) -> RequestBuilder {
+        self.headers.set(header);
+        self
+    }
+
+    /// Set all of the given headers on the request.
+    pub fn headers(mut self, headers: Headers) -> RequestBuilder {
+        self.headers.extend(headers.iter());
+        self
+    }
+
+    /// Set the body of the request.
+    ///
+    /// Accepts a `&str`, `String`, `&[u8]`, `Vec<u8>` or a `RequestBody`
+    /// streaming from a reader or a file. A body streaming from a reader is
+    /// shared by clones of the builder, and can only be sent once.
+    pub fn body<B: Into<RequestBody>>(mut self, body: B) -> RequestBuilder {
+        self.body = body.into();
+        self
+    }
+
+    /// Send the body with `Transfer-Encoding: chunked`, in chunks of at most
+    /// `chunk_size` bytes, rather than with a Content-Length.
+    ///
+    /// Panics if `chunk_size` is zero.
+    pub fn chunked(mut self, chunk_size: usize) -> RequestBuilder {
+        assert!(chunk_size > 0, "chunk size must be greater than zero");
+        self.chunk_size = Some(chunk_size);
+        self
+    }
+
+    /// Inject the given faults into the body as the Handler reads it, such
+    /// as fragmented reads, errors and an early EOF. Offsets are counted from
+    /// the start of the body, after any chunked encoding.
+    pub fn faults(mut self, faults: Faults) -> RequestBuilder {
+        self.faults = faults;
+        self
+    }
+
+    /// Set a JSON body on the request, serialized from the given value,
+    /// along with an `application/json` Content-Type header.
+    ///
+    /// Panics if the value cannot be serialized.
+    #[cfg(feature = "json")]
+    pub fn json<T: Serialize>(self, value: &T) -> RequestBuilder {
+        let body = match serde_json::to_vec(value) {
+            Ok(body) => body,
+            Err(err) => panic!("failed to serialize JSON body: {}", err),
+        };
+        self.header(headers::ContentType::json()).body(body)
+    }
+
+    /// Set a URL-encoded form body on the request, along with its
+    /// Content-Type header.
+    pub fn form(self, form: Form) -> RequestBuilder {
+        self.header(headers::ContentType(form.content_type()))
+            .body(form.encode())
+    }
+
+    /// Set a multipart/form-data body on the request, along with its
+    /// Content-Type header.
+    pub fn multipart(self, multipart: Multipart) -> RequestBuilder {
+        self.header(headers::ContentType(multipart.content_type()))
+            .body(multipart.to_bytes())
+    }
+
+    /// Set the address of the client making the request, which is
+    /// `127.0.0.1:3000` by default. IPv6 addresses are supported.
+    pub fn remote_addr(mut self, addr: SocketAddr) -> RequestBuilder {
+        self.remote_addr = addr;
+        self
+    }
+
+    /// Insert a value into the extensions of the request before it is
+    /// passed to the Handler.
+    pub fn extension<K>(mut self, value: K::Value) -> RequestBuilder
+    where K: Key, K::Value: Clone + Send + Sync {
+        self.extensions.push(Arc::new(move |extensions: &mut TypeMap| {
+            extensions.insert::<K>(value.clone());
+        }));
+        self
+    }
+
+    /// Call the given function with the extensions of the request before it
+    /// is passed to the Handler, to insert values which cannot be cloned.
+    ///
+    /// The function is called each time the request is sent.
+    pub fn extensions<F>(mut self, f: F) -> RequestBuilder
+    where F: Fn(&mut TypeMap) + Send + Sync + 'static {
+        self.extensions.push(Arc::new(f));
+        self
+    }
+
+    // The URL of the request, without the query added by `query`.
+    pub(crate) fn url_string(&self) -> &str {
+        &self.url
+    }
+
+    pub(crate) fn header_map(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// Constructs an Iron::Request from the current state of the builder and
+    /// passes it to the `handle` method on the given Handler.
+    ///
+    /// Panics if the request cannot be constructed; see `try_send`.
+    pub fn send<H: Handler + ?Sized>(&self, handler: &H) -> IronResult<Response> {
+        match self.try_send(handler) {
+            Ok(response) => Ok(response),
+            Err(TestError::Handler { error, .. }) => Err(error),
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Constructs an Iron::Request from the current state of the builder and
+    /// passes it to the `handle` method on the given Handler, returning an
+    /// error rather than panicking if the request cannot be constructed.
+    pub fn try_send<H: Handler + ?Sized>(&self, handler: &H) -> Result<Response, TestError> {
+        let (request, result) = try!(self.try_with_request_formatted(|req| handler.handle(req)));
+        result.map_err(|err| TestError::Handler { request: request, error: err })
+    }
+
+    /// Constructs an Iron::Request from the current state of the builder and
+    /// passes it to the given function instead of a Handler, to test
+    /// functions and middleware which take a `&mut Request`. The request
+    /// only lives for the duration of the call.
+    ///
+    /// Panics if the request cannot be constructed; see `try_with_request`.
+    pub fn with_request<F, T>(&self, f: F) -> T
+    where F: for<'a, 'b> FnOnce(&mut Request<'a, 'b>) -> T {
+        match self.try_with_request(f) {
+            Ok(result) => result,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Constructs an Iron::Request from the current state of the builder and
+    /// passes it to the given function, returning an error rather than
+    /// panicking if the request cannot be constructed.
+    pub fn try_with_request<F, T>(&self, f: F) -> Result<T, TestError>
+    where F: for<'a, 'b> FnOnce(&mut Request<'a, 'b>) -> T {
+        self.try_with_request_formatted(f).map(|(_, result)| result)
+    }
+
+    // Construct the request and pass it to the given function, returning the
+    // result along with the request formatted for errors.
+    fn try_with_request_formatted<F, T>(&self, f: F) -> Result<(String, T), TestError>
+    where F: for<'a, 'b> FnOnce(&mut Request<'a, 'b>) -> T {
+        let (request, data, protocol) = try!(self.prepare());
+        match with_request(data, self.remote_addr, &protocol, &self.extensions, f) {
+            Ok(result) => Ok((request, result)),
+            Err(ConstructError::Parse(err)) => Err(TestError::Parse { request: request, error: err }),
+            Err(ConstructError::Rejected(reason)) => Err(TestError::InvalidRequest { request: request, reason: reason }),
+        }
+    }
+
+    // Write the raw request to be parsed by hyper, returning it along with the
+    // request formatted for errors and the protocol of its URL.
+    fn prepare(&self) -> Result<(String, Box<Read + Send>, iron::Protocol), TestError> {
+        let request = format!("{} {}", self.method, self.url);
+
+        let mut url = try!(url::Url::parse(&self.url).map_err(|err| {
+            TestError::InvalidUrl { request: request.clone(), reason: err.to_string() }
+        }));
+        if !self.query.is_empty() {
+            url.query_pairs_mut().extend_pairs(self.query.iter());
+        }
+        let url = try!(Url::from_generic_url(url).map_err(|reason| {
+            TestError::InvalidUrl { request: request.clone(), reason: reason }
+        }));
+
+        let protocol = match url.scheme() {
+            "http" => iron::Protocol::http(),
+            "https" => iron::Protocol::https(),
+            scheme => return Err(TestError::UnsupportedScheme {
+                request: request,
+                scheme: scheme.to_owned(),
+            }),
+        };
+
+        if let method::Extension(ref name) = self.method {
+            if name.is_empty() || !name.bytes().all(is_token) {
+                return Err(TestError::InvalidMethod {
+                    request: request,
+                    reason: format!("{:?} is not a valid method name", name),
+                });
+            }
+        }
+        if self.method == method::Trace && !self.body.is_empty() {
+            return Err(TestError::InvalidMethod {
+                request: request,
+                reason: "a TRACE request must not have a body".to_owned(),
+            });
+        }
+
+        let mut buffer = String::new();
+        buffer.push_str(&format!("{} {} HTTP/1.1\r\n", &self.method, url));
+        match self.chunk_size {
+            Some(_) => buffer.push_str("Transfer-Encoding: chunked\r\n"),
+            // TRACE and CONNECT requests have no body unless one is given.
+            None if self.body.is_empty() && (self.method == method::Trace || self.method == method::Connect) => (),
+            None => buffer.push_str(&format!("Content-Length: {}\r\n", self.body.len())),
+        }
+        if let Err(name) = write_headers(&mut buffer, &self.headers) {
+            return Err(TestError::MalformedHeader { request: request, header: name });
+        }
+        if !self.headers.has::<headers::UserAgent>() {
+            buffer.push_str(&format!("User-Agent: iron-test\r\n"));
+        }
+        buffer.push_str("\r\n");
+
+        let body = match self.chunk_size {
+            Some(chunk_size) => self.body.to_chunked_reader(chunk_size),
+            None => self.body.to_reader(),
+        };
+        let body = try!(body.map_err(|err| TestError::Body { request: request.clone(), error: err }));
+        let body = Faulty::new(body, self.faults.clone());
+        let data = Cursor::new(buffer.into_bytes()).chain(body);
+        Ok((request, Box::new(data), protocol))
+    }
+}
+
+// Parse a request from the given data with hyper, construct an iron Request
+// from it and pass it to the Handler.
+pub(crate) fn dispatch<R, H>(data: R,
+                             remote_addr: SocketAddr,
+                             protocol: &iron::Protocol,
+                             extensions: &[Arc<Fn(&mut TypeMap) + Send + Sync>],
+                             handler: &H) -> RawOutcome
+where R: Read + Send + 'static, H: Handler + ?Sized {
+    match with_request(data, remote_addr, protocol, extensions, |req| handler.handle(req)) {
+        Ok(result) => RawOutcome::Handled(result),
+        Err(ConstructError::Parse(err)) => RawOutcome::ParseFailed(err),
+        Err(ConstructError::Rejected(reason)) => RawOutcome::Rejected(reason),
+    }
+}
+
+// Why an iron Request could not be constructed from the raw request.
+enum ConstructError {
+    Parse(hyper::Error),
+    Rejected(String),
+}
+
+// Parse a request from the given data with hyper, construct an iron Request
+// from it and pass it to the given function. The stream the request reads its
+// body from lives on the stack, so the request cannot outlive the call.
+fn with_request<R, F, T>(data: R,
+                         remote_addr: SocketAddr,
+                         protocol: &iron::Protocol,
+                         extensions: &[Arc<Fn(&mut TypeMap) + Send + Sync>],
+                         f: F) -> Result<T, ConstructError>
+where R: Read + Send + 'static, F: for<'a, 'b> FnOnce(&mut Request<'a, 'b>) -> T {
     // From iron 0.5.x, iron::Request contains private field. So, it is not good to
     // create iron::Request directly. Make http request and parse it with hyper,
     // and make iron::Request from hyper::client::Request.
-    let mut buffer = String::new();
-    buffer.push_str(&format!("{} {} HTTP/1.1\r\n", &method, url));
-    buffer.push_str(&format!("Content-Length: {}\r\n", body.len() as u64));
+    let local_addr = "127.0.0.1:3000".parse().unwrap();
+    let mut stream = MockStream::with_peer_addr(RequestData(data), remote_addr);
+    let mut buf_reader = BufReader::new(&mut stream as &mut NetworkStream);
+    // hyper 0.10 panics with `unreachable!` when a request has conflicting or
+    // non-numeric Content-Length headers, so report that as a bad header.
+    let reader = &mut buf_reader;
+    let parsed = panic::catch_unwind(AssertUnwindSafe(once(move || {
+        hyper::server::Request::new(reader, remote_addr)
+    })));
+    let http_request = match parsed {
+        Ok(Ok(http_request)) => http_request,
+        Ok(Err(err)) => return Err(ConstructError::Parse(err)),
+        Err(_) => return Err(ConstructError::Parse(hyper::Error::Header)),
+    };
+    let mut req = try!(Request::from_http(http_request, local_addr, protocol).map_err(ConstructError::Rejected));
+
+    for extension in extensions.iter() {
+        extension(&mut req.extensions);
+    }
+
+    Ok(f(&mut req))
+}
+
+// Let a closure which moves a borrow out of its environment be inferred as
+// FnOnce, which it is not when passed straight to `AssertUnwindSafe`.
+fn once<T, F: FnOnce() -> T>(f: F) -> F {
+    f
+}
+
+// Write each header on its own line, returning the name of the first header
+// which cannot be written.
+fn write_headers(buffer: &mut String, headers: &Headers) -> Result<(), String> {
     for header in headers.iter() {
-        buffer.push_str(&format!("{}: {}\r\n", header.name(), header.value_string()));
+        let name = header.name();
+        // Writing a header fails if any of its raw values are not utf8.
+        if name.is_empty() || !name.bytes().all(is_token) || write!(buffer, "{}", header).is_err() {
+            return Err(name.to_owned());
+        }
     }
-    if !headers.has::<headers::UserAgent>() {
-        buffer.push_str(&format!("User-Agent: iron-test\r\n"));
+    Ok(())
+}
+
+// Whether the byte may appear in a header name, as defined by RFC 7230.
+fn is_token(byte: u8) -> bool {
+    match byte {
+        b'a'...b'z' | b'A'...b'Z' | b'0'...b'9' => true,
+        b'!' | b'#' | b'$' | b'%' | b'&' | b'\'' | b'*' | b'+' |
+        b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~' => true,
+        _ => false,
     }
-    buffer.push_str("\r\n");
-    buffer.push_str(body);
+}
 
-    let addr = "127.0.0.1:3000".parse().unwrap();
-    let protocol = match url.scheme() {
-        "http" => iron::Protocol::http(),
-        "https" => iron::Protocol::https(),
-        _ => panic!("unknown protocol {}", url.scheme()),
-    };
+// The raw request read by hyper, discarding anything written back.
+struct RequestData<R>(R);
 
-    let mut stream = MockStream::new(Cursor::new(buffer.as_bytes().to_vec()));
-    let mut buf_reader = BufReader::new(&mut stream as &mut NetworkStream);
-    let http_request = hyper::server::Request::new(&mut buf_reader, addr).unwrap();
-    let mut req = Request::from_http(http_request, addr, &protocol).unwrap();
+impl<R: Read> Read for RequestData<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
 
-    handler.handle(&mut req)
+impl<R> Write for RequestData<R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -89,7 +598,8 @@ mod test {
     extern crate router;
     extern crate urlencoded;
 
-    use iron::headers::Headers;
+    use iron::headers::{Authorization, Bearer, Headers};
+    use iron::middleware::BeforeMiddleware;
     use iron::mime::Mime;
     use iron::prelude::*;
     use iron::{Handler, headers, status};
@@ -98,6 +608,11 @@ mod test {
 
     use self::urlencoded::UrlEncodedBody;
 
+    use std::io::{self, Read};
+    use std::sync::Mutex;
+
+    use {Form, Multipart, ProjectBuilder, RequestBody, TestError};
+
     use super::*;
 
     struct HelloWorldHandler;
@@ -179,6 +694,122 @@ mod test {
         }
     }
 
+    struct QueryHandler;
+
+    impl Handler for QueryHandler {
+        fn handle(&self, req: &mut Request) -> IronResult<Response> {
+            Ok(Response::with((status::Ok, req.url.query().unwrap_or("").to_owned())))
+        }
+    }
+
+    struct MethodHandler;
+
+    impl Handler for MethodHandler {
+        fn handle(&self, req: &mut Request) -> IronResult<Response> {
+            Ok(Response::with((status::Ok, req.method.to_string())))
+        }
+    }
+
+    struct MethodBodyHandler;
+
+    impl Handler for MethodBodyHandler {
+        fn handle(&self, req: &mut Request) -> IronResult<Response> {
+            let mut body = String::new();
+            req.body.read_to_string(&mut body).unwrap();
+            let content_length = req.headers.get::<headers::ContentLength>()
+                .map_or("none".to_owned(), |len| len.to_string());
+            let response = format!("{} {} {} {}", req.method, req.url, content_length, body);
+
+            Ok(Response::with((status::Ok, response)))
+        }
+    }
+
+    struct EchoHandler;
+
+    impl Handler for EchoHandler {
+        fn handle(&self, req: &mut Request) -> IronResult<Response> {
+            let mut body = Vec::new();
+            req.body.read_to_end(&mut body).unwrap();
+            let content_length = req.headers.get::<headers::ContentLength>().unwrap().0;
+            body.extend(format!(" ({})", content_length).into_bytes());
+
+            Ok(Response::with((status::Ok, body)))
+        }
+    }
+
+    struct ChunkedHandler;
+
+    impl Handler for ChunkedHandler {
+        fn handle(&self, req: &mut Request) -> IronResult<Response> {
+            let mut body = String::new();
+            let result = req.body.read_to_string(&mut body);
+            let encoding = req.headers.get::<headers::TransferEncoding>().unwrap();
+            let response = match result {
+                Ok(_) => format!("{} ({}, {})", body, encoding, req.headers.has::<headers::ContentLength>()),
+                Err(err) => format!("{} ({})", body, err),
+            };
+
+            Ok(Response::with((status::Ok, response)))
+        }
+    }
+
+    struct BodyResultHandler;
+
+    impl Handler for BodyResultHandler {
+        fn handle(&self, req: &mut Request) -> IronResult<Response> {
+            let mut body = String::new();
+            let response = match req.body.read_to_string(&mut body) {
+                Ok(_) => body,
+                Err(err) => format!("{} ({:?})", body, err.kind()),
+            };
+
+            Ok(Response::with((status::Ok, response)))
+        }
+    }
+
+    struct ErrorHandler;
+
+    impl Handler for ErrorHandler {
+        fn handle(&self, _: &mut Request) -> IronResult<Response> {
+            let error = io::Error::new(io::ErrorKind::Other, "database unavailable");
+            Err(IronError::new(error, status::ServiceUnavailable))
+        }
+    }
+
+    struct ContentTypeHandler;
+
+    impl Handler for ContentTypeHandler {
+        fn handle(&self, req: &mut Request) -> IronResult<Response> {
+            let content_type = req.headers.get::<headers::ContentType>().unwrap();
+            let mut body = Vec::new();
+            req.body.read_to_end(&mut body).unwrap();
+
+            Ok(Response::with((status::Ok, format!("{} {}", content_type, body.len()))))
+        }
+    }
+
+    struct RemoteAddrHandler;
+
+    impl Handler for RemoteAddrHandler {
+        fn handle(&self, req: &mut Request) -> IronResult<Response> {
+            Ok(Response::with((status::Ok, req.remote_addr.to_string())))
+        }
+    }
+
+    #[derive(Clone)]
+    struct CurrentUser(String);
+
+    impl Key for CurrentUser { type Value = CurrentUser; }
+
+    struct CurrentUserHandler;
+
+    impl Handler for CurrentUserHandler {
+        fn handle(&self, req: &mut Request) -> IronResult<Response> {
+            let user = req.extensions.get::<CurrentUser>().unwrap();
+            Ok(Response::with((status::Ok, user.0.clone())))
+        }
+    }
+
     #[test]
     fn test_get() {
         let response = get("http://localhost:3000", Headers::new(), &HelloWorldHandler);
@@ -263,6 +894,62 @@ mod test {
         assert_eq!(result, b"");
     }
 
+    #[test]
+    fn test_trace() {
+        let response = trace("http://localhost:3000/users", Headers::new(), &MethodBodyHandler);
+        let result = extract_body_to_string(response.unwrap());
+
+        assert_eq!(result, "TRACE http://localhost:3000/users none ");
+    }
+
+    #[test]
+    fn test_trace_with_body() {
+        let err = RequestBuilder::trace("http://localhost:3000/users")
+            .body("body")
+            .try_send(&MethodBodyHandler)
+            .unwrap_err();
+
+        assert_eq!(err.to_string(),
+                   "invalid method in request `TRACE http://localhost:3000/users`: \
+                    a TRACE request must not have a body");
+    }
+
+    #[test]
+    fn test_connect() {
+        let response = connect("http://localhost:443", Headers::new(), &MethodBodyHandler);
+        let result = extract_body_to_string(response.unwrap());
+
+        assert_eq!(result, "CONNECT http://localhost:443/ none ");
+    }
+
+    #[test]
+    fn test_extension_methods() {
+        let response = RequestBuilder::custom("PROPFIND", "http://localhost:3000/files")
+            .body("<propfind/>")
+            .send(&MethodBodyHandler);
+        let result = extract_body_to_string(response.unwrap());
+        assert_eq!(result, "PROPFIND http://localhost:3000/files 11 <propfind/>");
+
+        let response = request(method::Extension("PURGE".to_owned()), "http://localhost:3000/cache",
+                               "", Headers::new(), &MethodBodyHandler);
+        let result = extract_body_to_string(response.unwrap());
+        assert_eq!(result, "PURGE http://localhost:3000/cache 0 ");
+
+        let builder = RequestBuilder::custom("GET", "http://localhost:3000/");
+        assert_eq!(extract_body_to_string(builder.send(&MethodHandler).unwrap()), "GET");
+    }
+
+    #[test]
+    fn test_invalid_extension_method() {
+        let err = RequestBuilder::custom("BAD METHOD", "http://localhost:3000/")
+            .try_send(&MethodHandler)
+            .unwrap_err();
+
+        assert_eq!(err.to_string(),
+                   "invalid method in request `BAD METHOD http://localhost:3000/`: \
+                    \"BAD METHOD\" is not a valid method name");
+    }
+
     #[test]
     fn test_user_agent_not_provided() {
         let headers = Headers::new();
@@ -289,4 +976,382 @@ mod test {
 
         assert_eq!(result, b"");
     }
+
+    #[test]
+    fn test_builder_get() {
+        let response = RequestBuilder::get("http://localhost:3000").send(&HelloWorldHandler);
+        let result = extract_body_to_bytes(response.unwrap());
+
+        assert_eq!(result, b"Hello, world!");
+    }
+
+    #[test]
+    fn test_builder_post() {
+        let mime: Mime = "application/x-www-form-urlencoded".parse().unwrap();
+        let response = RequestBuilder::post("http://localhost:3000/users")
+            .header(headers::ContentType(mime))
+            .body("first_name=Example&last_name=User")
+            .send(&PostHandler);
+        let result = extract_body_to_bytes(response.unwrap());
+
+        assert_eq!(result, b"Example User");
+    }
+
+    #[test]
+    fn test_builder_query() {
+        let response = RequestBuilder::get("http://localhost:3000/users?page=2")
+            .query("name", "Example User")
+            .send(&QueryHandler);
+        let result = extract_body_to_string(response.unwrap());
+
+        assert_eq!(result, "page=2&name=Example+User");
+    }
+
+    #[test]
+    fn test_builder_clone_variants() {
+        let builder = RequestBuilder::get("http://localhost:3000/");
+        let with_agent = builder.clone()
+            .header(headers::UserAgent("CustomAgent/1.0".to_owned()));
+
+        let response = builder.send(&UserAgentHandler);
+        assert_eq!(extract_body_to_string(response.unwrap()), "iron-test");
+
+        let response = with_agent.send(&UserAgentHandler);
+        assert_eq!(extract_body_to_string(response.unwrap()), "CustomAgent/1.0");
+
+        let response = with_agent.method(method::Put).send(&MethodHandler);
+        assert_eq!(extract_body_to_string(response.unwrap()), "PUT");
+    }
+
+    #[test]
+    fn test_builder_extension() {
+        let response = RequestBuilder::get("http://localhost:3000/me")
+            .extension::<CurrentUser>(CurrentUser("Example User".to_owned()))
+            .send(&CurrentUserHandler);
+        let result = extract_body_to_string(response.unwrap());
+
+        assert_eq!(result, "Example User");
+    }
+
+    #[test]
+    fn test_builder_extensions() {
+        struct Pool(Mutex<Vec<String>>);
+
+        impl Key for Pool { type Value = Pool; }
+
+        struct PoolHandler;
+
+        impl Handler for PoolHandler {
+            fn handle(&self, req: &mut Request) -> IronResult<Response> {
+                let pool = req.extensions.get::<Pool>().unwrap();
+                let connection = pool.0.lock().unwrap().pop().unwrap_or_default();
+                Ok(Response::with((status::Ok, connection)))
+            }
+        }
+
+        let builder = RequestBuilder::get("http://localhost:3000/")
+            .extensions(|extensions| {
+                extensions.insert::<Pool>(Pool(Mutex::new(vec!["connection".to_owned()])));
+            });
+
+        for _ in 0..2 {
+            let result = extract_body_to_string(builder.send(&PoolHandler).unwrap());
+            assert_eq!(result, "connection");
+        }
+    }
+
+    fn bearer_token(req: &Request) -> Option<String> {
+        req.headers.get::<Authorization<Bearer>>().map(|auth| auth.token.clone())
+    }
+
+    struct RequireToken;
+
+    impl BeforeMiddleware for RequireToken {
+        fn before(&self, req: &mut Request) -> IronResult<()> {
+            match bearer_token(req) {
+                Some(_) => Ok(()),
+                None => Err(IronError::new(io::Error::new(io::ErrorKind::PermissionDenied, "no token"),
+                                           status::Unauthorized)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_builder_with_request() {
+        let builder = RequestBuilder::get("http://localhost:3000/users?page=2")
+            .header(Authorization(Bearer { token: "secret".to_owned() }));
+
+        assert_eq!(builder.with_request(|req| req.url.query().map(|q| q.to_owned())), Some("page=2".to_owned()));
+        assert_eq!(builder.with_request(|req| bearer_token(req)), Some("secret".to_owned()));
+        assert!(builder.with_request(|req| RequireToken.before(req)).is_ok());
+
+        let builder = RequestBuilder::get("http://localhost:3000/users");
+        assert!(builder.with_request(|req| RequireToken.before(req)).is_err());
+    }
+
+    #[test]
+    fn test_builder_with_request_plugins_and_body() {
+        let name = RequestBuilder::post("http://localhost:3000/users")
+            .form(Form::new().field("name", "Example User"))
+            .with_request(|req| req.get_ref::<UrlEncodedBody>().unwrap()["name"][0].clone());
+        assert_eq!(name, "Example User");
+
+        let response = RequestBuilder::post("http://localhost:3000/users")
+            .body("body")
+            .with_request(|req| EchoHandler.handle(req))
+            .unwrap();
+        assert_eq!(extract_body_to_string(response), "body (4)");
+    }
+
+    #[test]
+    fn test_builder_try_with_request() {
+        match RequestBuilder::get("localhost:3000").try_with_request(|_| ()) {
+            Err(TestError::InvalidUrl { .. }) => (),
+            _ => panic!("expected an invalid URL"),
+        }
+    }
+
+    #[test]
+    fn test_builder_binary_body() {
+        let response = RequestBuilder::put("http://localhost:3000/upload")
+            .body(&[0xff, 0x00, 0xfe][..])
+            .send(&EchoHandler);
+        let result = extract_body_to_bytes(response.unwrap());
+
+        assert_eq!(result, b"\xff\x00\xfe (3)");
+    }
+
+    #[test]
+    fn test_builder_utf8_body_length() {
+        let response = RequestBuilder::post("http://localhost:3000/upload")
+            .body("caf\u{e9}")
+            .send(&EchoHandler);
+        let result = extract_body_to_string(response.unwrap());
+
+        assert_eq!(result, "caf\u{e9} (5)");
+    }
+
+    #[test]
+    fn test_builder_reader_body() {
+        let reader = Cursor::new(b"streamed body and then some".to_vec());
+        let response = RequestBuilder::post("http://localhost:3000/upload")
+            .body(RequestBody::reader(reader, 13))
+            .send(&EchoHandler);
+        let result = extract_body_to_string(response.unwrap());
+
+        assert_eq!(result, "streamed body (13)");
+    }
+
+    #[test]
+    fn test_builder_reader_body_sent_twice() {
+        let reader = Cursor::new(b"streamed body".to_vec());
+        let builder = RequestBuilder::post("http://localhost:3000/upload").body(RequestBody::reader(reader, 13));
+        assert!(builder.clone().try_send(&EchoHandler).is_ok());
+
+        match builder.try_send(&EchoHandler) {
+            Err(err @ TestError::Body { .. }) => assert!(err.to_string().contains("can only be sent once")),
+            other => panic!("expected a body error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_builder_chunked_body() {
+        let builder = RequestBuilder::post("http://localhost:3000/upload")
+            .body("streamed body")
+            .chunked(4);
+        let result = extract_body_to_string(builder.send(&ChunkedHandler).unwrap());
+        assert_eq!(result, "streamed body (chunked, false)");
+
+        let reader = Cursor::new(b"streamed body and then some".to_vec());
+        let builder = RequestBuilder::post("http://localhost:3000/upload")
+            .body(RequestBody::reader(reader, 13))
+            .chunked(1024);
+        let result = extract_body_to_string(builder.send(&ChunkedHandler).unwrap());
+        assert_eq!(result, "streamed body (chunked, false)");
+
+        let builder = RequestBuilder::post("http://localhost:3000/upload").chunked(1);
+        let result = extract_body_to_string(builder.send(&ChunkedHandler).unwrap());
+        assert_eq!(result, " (chunked, false)");
+    }
+
+    #[test]
+    fn test_builder_faults() {
+        let send = |builder: RequestBuilder| extract_body_to_string(builder.send(&BodyResultHandler).unwrap());
+        let builder = RequestBuilder::post("http://localhost:3000/upload").body("hello world");
+
+        assert_eq!(send(builder.clone().faults(Faults::new().fragments(1))), "hello world");
+        assert_eq!(send(builder.clone().faults(Faults::new().random_fragments(3, 7))), "hello world");
+        assert_eq!(send(builder.clone().chunked(2).faults(Faults::new().fragments(1))), "hello world");
+
+        // read_to_string retries reads which were interrupted.
+        let faults = Faults::new().error_at(5, io::ErrorKind::Interrupted);
+        assert_eq!(send(builder.clone().faults(faults)), "hello world");
+
+        let faults = Faults::new().error_at(5, io::ErrorKind::WouldBlock);
+        assert_eq!(send(builder.clone().faults(faults)), "hello (WouldBlock)");
+        let faults = Faults::new().error_at(5, io::ErrorKind::ConnectionReset);
+        assert_eq!(send(builder.clone().faults(faults)), "hello (ConnectionReset)");
+
+        // hyper 0.10 reports an early EOF as an `Other` error, or as an invalid
+        // chunk size when the body is chunked.
+        assert_eq!(send(builder.clone().faults(Faults::new().eof_at(5))), "hello (Other)");
+        assert_eq!(send(builder.clone().chunked(2).faults(Faults::new().eof_at(7))), "he (InvalidInput)");
+    }
+
+    #[test]
+    fn test_builder_form_faults() {
+        let mut router = router::Router::new();
+        router.put("/users/:id", UpdateHandler, "update");
+        let builder = RequestBuilder::put("http://localhost:3000/users/3")
+            .form(Form::new().field("first_name", "Example").field("last_name", "User"));
+
+        let response = builder.clone().faults(Faults::new().random_fragments(4, 1)).send(&router);
+        assert_eq!(extract_body_to_string(response.unwrap()), "Example User 3");
+
+        let response = RequestBuilder::post("http://localhost:3000/users")
+            .form(Form::new().field("first_name", "Example").field("last_name", "User"))
+            .faults(Faults::new().eof_at(10))
+            .send(&|req: &mut Request| {
+                Ok(Response::with((status::Ok, req.get_ref::<UrlEncodedBody>().is_err().to_string())))
+            });
+        assert_eq!(extract_body_to_string(response.unwrap()), "true");
+    }
+
+    #[test]
+    fn test_builder_file_body() {
+        let p = ProjectBuilder::new("upload").file("image.png", vec![0x89, b'P', b'N', b'G']);
+        p.build();
+
+        let body = RequestBody::file(p.root().join("image.png")).unwrap();
+        let builder = RequestBuilder::post("http://localhost:3000/upload").body(body);
+
+        // The file is opened again for each request.
+        for _ in 0..2 {
+            let result = extract_body_to_bytes(builder.send(&EchoHandler).unwrap());
+            assert_eq!(result, b"\x89PNG (4)");
+        }
+    }
+
+    #[test]
+    fn test_try_get_invalid_url() {
+        let err = try_get("localhost:3000/users", Headers::new(), &HelloWorldHandler).unwrap_err();
+
+        match err {
+            TestError::InvalidUrl { .. } => (),
+            err => panic!("expected an invalid URL error, got {:?}", err),
+        }
+        assert_eq!(err.request(), "GET localhost:3000/users");
+    }
+
+    #[test]
+    fn test_try_get_unsupported_scheme() {
+        let err = try_get("ftp://localhost:3000/", Headers::new(), &HelloWorldHandler).unwrap_err();
+
+        assert_eq!(err.to_string(),
+                   "unsupported scheme `ftp` in request `GET ftp://localhost:3000/`; expected http or https");
+    }
+
+    #[test]
+    fn test_try_get_malformed_header() {
+        let mut headers = Headers::new();
+        headers.set_raw("X-Latin1", vec![b"caf\xe9".to_vec()]);
+        let err = try_get("http://localhost:3000/", headers, &HelloWorldHandler).unwrap_err();
+
+        match err {
+            TestError::MalformedHeader { ref header, .. } => assert_eq!(header, "X-Latin1"),
+            err => panic!("expected a malformed header error, got {:?}", err),
+        }
+
+        let mut headers = Headers::new();
+        headers.set_raw("Bad Header", vec![b"value".to_vec()]);
+        let err = try_get("http://localhost:3000/", headers, &HelloWorldHandler).unwrap_err();
+
+        assert_eq!(err.to_string(),
+                   "malformed header \"Bad Header\" in request `GET http://localhost:3000/`");
+    }
+
+    #[test]
+    fn test_try_get_handler_error() {
+        let err = try_get("http://localhost:3000/", Headers::new(), &ErrorHandler).unwrap_err();
+
+        match err {
+            TestError::Handler { ref error, .. } => {
+                assert_eq!(error.response.status, Some(status::ServiceUnavailable));
+            },
+            err => panic!("expected a handler error, got {:?}", err),
+        }
+        assert_eq!(err.to_string(),
+                   "handler returned an error for request `GET http://localhost:3000/`: database unavailable");
+    }
+
+    #[test]
+    fn test_get_handler_error() {
+        let err = get("http://localhost:3000/", Headers::new(), &ErrorHandler).unwrap_err();
+
+        assert_eq!(err.response.status, Some(status::ServiceUnavailable));
+    }
+
+    #[test]
+    #[should_panic(expected = "unsupported scheme `ftp`")]
+    fn test_get_unsupported_scheme_panics() {
+        let _ = get("ftp://localhost:3000/", Headers::new(), &HelloWorldHandler);
+    }
+
+    #[test]
+    fn test_builder_remote_addr() {
+        let response = RequestBuilder::get("http://localhost:3000/").send(&RemoteAddrHandler);
+        assert_eq!(extract_body_to_string(response.unwrap()), "127.0.0.1:3000");
+
+        let response = RequestBuilder::get("http://localhost:3000/")
+            .remote_addr("192.168.1.20:51234".parse().unwrap())
+            .send(&RemoteAddrHandler);
+        assert_eq!(extract_body_to_string(response.unwrap()), "192.168.1.20:51234");
+
+        let response = RequestBuilder::get("http://[::1]:3000/")
+            .remote_addr("[2001:db8::1]:51234".parse().unwrap())
+            .send(&RemoteAddrHandler);
+        assert_eq!(extract_body_to_string(response.unwrap()), "[2001:db8::1]:51234");
+    }
+
+    #[test]
+    fn test_builder_multipart() {
+        let multipart = Multipart::with_boundary("xyz")
+            .text("title", "Holiday")
+            .file("photo", "beach.png", "image/png".parse().unwrap(), vec![0x89, b'P', b'N', b'G']);
+        let len = multipart.to_bytes().len();
+
+        let response = RequestBuilder::post("http://localhost:3000/photos")
+            .multipart(multipart)
+            .send(&ContentTypeHandler);
+        let result = extract_body_to_string(response.unwrap());
+
+        assert_eq!(result, format!("multipart/form-data; boundary=xyz {}", len));
+    }
+
+    #[test]
+    fn test_builder_form() {
+        let mut router = router::Router::new();
+        router.put("/users/:id", UpdateHandler, "update");
+
+        let form = Form::new()
+            .field("first_name", "Zo\u{eb}")
+            .field("last_name", "User & Co");
+        let response = RequestBuilder::put("http://localhost:3000/users/3")
+            .form(form)
+            .send(&router);
+        let result = extract_body_to_string(response.unwrap());
+
+        assert_eq!(result, "Zo\u{eb} User & Co 3");
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_builder_json() {
+        let response = RequestBuilder::post("http://localhost:3000/users")
+            .json(&json!({"name": "Example User"}))
+            .send(&ContentTypeHandler);
+        let result = extract_body_to_string(response.unwrap());
+
+        assert_eq!(result, "application/json 23");
+    }
 }
@@ -0,0 +1,158 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// The body of a request made to an Iron Handler.
+///
+/// A body is either a buffer of bytes, a file, or a reader which is streamed
+/// into the request as the Handler reads it. A file is opened again each time
+/// the body is sent. A reader can only be read once, so clones of a body
+/// streaming from a reader share the same reader, and only the first request
+/// sent with it gets the body; later requests fail with `TestError::Body`.
+#[derive(Clone)]
+pub struct RequestBody {
+    kind: BodyKind,
+}
+
+#[derive(Clone)]
+enum BodyKind {
+    Bytes(Vec<u8>),
+    File(PathBuf, u64),
+    Reader(Arc<Mutex<Option<Box<Read + Send>>>>, u64),
+}
+
+impl RequestBody {
+    /// Create an empty body.
+    pub fn empty() -> RequestBody {
+        RequestBody::bytes(Vec::new())
+    }
+
+    /// Create a body from a buffer of bytes.
+    pub fn bytes<B: Into<Vec<u8>>>(bytes: B) -> RequestBody {
+        RequestBody { kind: BodyKind::Bytes(bytes.into()) }
+    }
+
+    /// Create a body which streams `len` bytes from the given reader.
+    ///
+    /// `len` is sent as the Content-Length of the request. The body can only
+    /// be sent once.
+    pub fn reader<R: Read + Send + 'static>(reader: R, len: u64) -> RequestBody {
+        RequestBody { kind: BodyKind::Reader(Arc::new(Mutex::new(Some(Box::new(reader)))), len) }
+    }
+
+    /// Create a body which streams the contents of the file at the given path.
+    ///
+    /// The file is opened again each time the body is sent, and its length
+    /// when the body is created is sent as the Content-Length.
+    pub fn file<P: AsRef<Path>>(path: P) -> io::Result<RequestBody> {
+        let path = path.as_ref().to_path_buf();
+        let len = try!(File::open(&path).and_then(|file| file.metadata())).len();
+        Ok(RequestBody { kind: BodyKind::File(path, len) })
+    }
+
+    /// The length of the body in bytes.
+    pub fn len(&self) -> u64 {
+        match self.kind {
+            BodyKind::Bytes(ref bytes) => bytes.len() as u64,
+            BodyKind::File(_, len) | BodyKind::Reader(_, len) => len,
+        }
+    }
+
+    /// Whether the body is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // A reader over the contents of the body, which fails if the file cannot
+    // be opened or the reader has already been sent.
+    pub(crate) fn to_reader(&self) -> io::Result<Box<Read + Send>> {
+        match self.kind {
+            BodyKind::Bytes(ref bytes) => Ok(Box::new(Cursor::new(bytes.clone()))),
+            BodyKind::File(ref path, len) => {
+                let file = try!(File::open(path));
+                Ok(Box::new(file.take(len)))
+            },
+            BodyKind::Reader(ref reader, len) => match reader.lock().unwrap().take() {
+                Some(reader) => Ok(Box::new(reader.take(len))),
+                None => Err(io::Error::new(io::ErrorKind::Other,
+                                           "a body streamed from a reader can only be sent once; \
+                                            use RequestBody::bytes or RequestBody::file to send it again")),
+            },
+        }
+    }
+
+    // A reader over the contents of the body with the chunked transfer coding,
+    // in chunks of at most `chunk_size` bytes.
+    pub(crate) fn to_chunked_reader(&self, chunk_size: usize) -> io::Result<Box<Read + Send>> {
+        Ok(Box::new(ChunkedReader {
+            body: try!(self.to_reader()),
+            chunk_size: chunk_size,
+            chunk: Cursor::new(Vec::new()),
+            done: false,
+        }))
+    }
+}
+
+impl fmt::Debug for RequestBody {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            BodyKind::Bytes(ref bytes) => write!(f, "RequestBody({:?})", String::from_utf8_lossy(bytes)),
+            BodyKind::File(ref path, len) => write!(f, "RequestBody(<file {:?} of {} bytes>)", path, len),
+            BodyKind::Reader(_, len) => write!(f, "RequestBody(<reader of {} bytes>)", len),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for RequestBody {
+    fn from(body: &'a str) -> RequestBody {
+        RequestBody::bytes(body)
+    }
+}
+
+impl From<String> for RequestBody {
+    fn from(body: String) -> RequestBody {
+        RequestBody::bytes(body)
+    }
+}
+
+impl<'a> From<&'a [u8]> for RequestBody {
+    fn from(body: &'a [u8]) -> RequestBody {
+        RequestBody::bytes(body)
+    }
+}
+
+impl From<Vec<u8>> for RequestBody {
+    fn from(body: Vec<u8>) -> RequestBody {
+        RequestBody::bytes(body)
+    }
+}
+
+struct ChunkedReader {
+    body: Box<Read + Send>,
+    chunk_size: usize,
+    chunk: Cursor<Vec<u8>>,
+    done: bool,
+}
+
+impl Read for ChunkedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = try!(self.chunk.read(buf));
+            if n > 0 || self.done || buf.is_empty() {
+                return Ok(n);
+            }
+
+            let mut data = Vec::new();
+            try!((&mut self.body).take(self.chunk_size as u64).read_to_end(&mut data));
+
+            let mut chunk = format!("{:x}\r\n", data.len()).into_bytes();
+            self.done = data.is_empty();
+            chunk.extend(data);
+            chunk.extend(b"\r\n");
+            self.chunk = Cursor::new(chunk);
+        }
+    }
+}
+
@@ -1,20 +1,400 @@
+use encoding::{DecoderTrap, Encoding};
+use encoding::all::UTF_8;
+use encoding::label::encoding_from_whatwg_label;
+
+use iron::headers::{self, Header, HeaderFormat};
+use iron::mime::Attr;
 use iron::prelude::*;
+use iron::{status, Headers};
+
+use error::{BodyError, DecodeError};
+
+use std::error::Error;
+use std::fmt;
+#[cfg(feature = "compression")]
+use std::io;
+
+#[cfg(feature = "compression")]
+use compression;
 
-/// Extracts a utf8 response body to a String.
+#[cfg(feature = "json")]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "json")]
+use serde_json::Value;
+
+#[cfg(feature = "json")]
+use json::{self, JsonError};
+#[cfg(feature = "json")]
+pub use json::{JsonDiff, JsonMatcher};
+
+/// Extracts a response body to a String, decoded using the charset of its
+/// Content-Type.
+///
+/// Falls back to utf8, replacing invalid sequences with U+FFFD, if the
+/// charset is not supported or the body is not valid in it; see
+/// `extract_body_to_text`.
 pub fn extract_body_to_string(response: Response) -> String {
-    let result = extract_body_to_bytes(response);
-    String::from_utf8(result).unwrap()
+    let headers = response.headers.clone();
+    decode_body_lossy(&headers, &extract_body_to_bytes(response))
+}
+
+/// Extracts a response body to a String, decoded using the charset of its
+/// Content-Type.
+///
+/// When the response has no charset, or a utf8 charset, invalid utf8
+/// sequences are replaced with U+FFFD and a warning is logged, so
+/// `DecodeError::Malformed` is only returned for other charsets.
+pub fn extract_body_to_text(response: Response) -> Result<String, DecodeError> {
+    let headers = response.headers.clone();
+    decode_body(&headers, &extract_body_to_bytes(response))
 }
 
 /// Extracts a response body to a Vector of bytes.
+///
+/// Errors returned while writing the body are ignored; see
+/// `try_extract_body_to_bytes`.
 pub fn extract_body_to_bytes(response: Response) -> Vec<u8> {
+    match try_extract_body_to_bytes(response) {
+        Ok(result) => result,
+        Err(err) => err.bytes,
+    }
+}
+
+/// Extracts a response body to a Vector of bytes, decoding it according to
+/// its Content-Encoding.
+///
+/// Supports gzip, deflate and brotli. Use `extract_body_to_bytes` for the raw
+/// encoded bytes.
+#[cfg(feature = "compression")]
+pub fn extract_body_to_decoded_bytes(response: Response) -> io::Result<Vec<u8>> {
+    let headers = response.headers.clone();
+    compression::decode(&headers, &extract_body_to_bytes(response))
+}
+
+/// Extracts a response body to a Vector of bytes, returning the bytes written
+/// so far along with the error if writing the body fails.
+pub fn try_extract_body_to_bytes(response: Response) -> Result<Vec<u8>, BodyError> {
     let mut result = Vec::new();
 
     if let Some(mut body) = response.body {
-        body.write_body(&mut result).ok();
+        if let Err(err) = body.write_body(&mut result) {
+            return Err(BodyError { bytes: result, error: err });
+        }
+    }
+
+    Ok(result)
+}
+
+/// Extracts a JSON response body, deserializing it into the given type.
+#[cfg(feature = "json")]
+pub fn extract_body_to_json<T: DeserializeOwned>(response: Response) -> Result<T, JsonError> {
+    json::from_body(&extract_body_to_bytes(response))
+}
+
+/// Extracts a JSON response body to a generic JSON value.
+#[cfg(feature = "json")]
+pub fn extract_body_to_json_value(response: Response) -> Result<Value, JsonError> {
+    extract_body_to_json(response)
+}
+
+/// Asserts that a JSON response body is equal to the expected document,
+/// reporting each difference by path.
+#[cfg(feature = "json")]
+pub fn assert_json_eq(response: Response, expected: Value) {
+    assert_json(response, &JsonMatcher::new(expected))
+}
+
+/// Asserts that a JSON response body contains the expected document, ignoring
+/// keys and array elements which are not expected.
+#[cfg(feature = "json")]
+pub fn assert_json_includes(response: Response, expected: Value) {
+    assert_json(response, &JsonMatcher::new(expected).subset())
+}
+
+/// Asserts that a JSON response body matches the given matcher.
+#[cfg(feature = "json")]
+pub fn assert_json(response: Response, matcher: &JsonMatcher) {
+    match extract_body_to_json_value(response) {
+        Ok(actual) => matcher.assert_matches(&actual),
+        Err(err) => panic!("{}", err),
+    }
+}
+
+/// A response from an Iron Handler, with its body buffered in memory.
+///
+/// Unlike `extract_body_to_bytes`, reading the body of a `TestResponse` does
+/// not lose its status and headers. The `assert_` methods can be chained, and
+/// print the full response when they fail.
+///
+/// A `TestResponse` can also be created from an `IronError` returned by a
+/// Handler, keeping the error so that it can be downcast to the type the
+/// Handler returned.
+pub struct TestResponse {
+    status: Option<status::Status>,
+    headers: Headers,
+    body: Vec<u8>,
+    error: Option<Box<Error + Send>>,
+}
+
+impl TestResponse {
+    /// Create a TestResponse, reading the body of the given Response.
+    pub fn new(response: Response) -> TestResponse {
+        let status = response.status;
+        let headers = response.headers.clone();
+        let body = extract_body_to_bytes(response);
+
+        TestResponse { status: status, headers: headers, body: body, error: None }
+    }
+
+    /// Create a TestResponse, reading the body of the given Response and
+    /// returning the bytes written so far along with the error if writing
+    /// the body fails.
+    pub fn try_new(response: Response) -> Result<TestResponse, BodyError> {
+        let status = response.status;
+        let headers = response.headers.clone();
+        let body = try!(try_extract_body_to_bytes(response));
+
+        Ok(TestResponse { status: status, headers: headers, body: body, error: None })
+    }
+
+    /// Create a TestResponse from the result of a request.
+    ///
+    /// If the Handler returned an error, the TestResponse holds the response
+    /// of the error, and the error itself is available from `error`.
+    pub fn from_result(result: IronResult<Response>) -> TestResponse {
+        match result {
+            Ok(response) => TestResponse::new(response),
+            Err(IronError { error, response }) => {
+                let mut response = TestResponse::new(response);
+                response.error = Some(error);
+                response
+            },
+        }
+    }
+
+    /// The error returned by the Handler, if it returned one.
+    pub fn error(&self) -> Option<&(Error + Send + 'static)> {
+        self.error.as_ref().map(|error| &**error)
+    }
+
+    /// The error returned by the Handler, if it returned one of the given type.
+    pub fn error_as<E: Error + 'static>(&self) -> Option<&E> {
+        self.error.as_ref().and_then(|error| error.downcast_ref::<E>())
+    }
+
+    /// Whether the Handler returned an error.
+    pub fn is_error(&self) -> bool {
+        self.error.is_some()
+    }
+
+    /// The status of the response.
+    pub fn status(&self) -> Option<status::Status> {
+        self.status
+    }
+
+    /// The headers of the response.
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// The header of the given type, if it was set on the response.
+    pub fn header<H: Header + HeaderFormat>(&self) -> Option<&H> {
+        self.headers.get::<H>()
+    }
+
+    /// The body of the response.
+    pub fn body_bytes(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// The body of the response, decoded according to its Content-Encoding.
+    ///
+    /// Supports gzip, deflate and brotli. `body_bytes` returns the raw encoded
+    /// bytes.
+    #[cfg(feature = "compression")]
+    pub fn decoded_body_bytes(&self) -> io::Result<Vec<u8>> {
+        compression::decode(&self.headers, &self.body)
+    }
+
+    /// The body of the response decoded according to its Content-Encoding,
+    /// and then as a String using the charset of its Content-Type.
+    #[cfg(feature = "compression")]
+    pub fn decoded_body_string(&self) -> io::Result<String> {
+        let body = try!(self.decoded_body_bytes());
+        decode_body(&self.headers, &body).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// The body of the response as a String, decoded using the charset of
+    /// its Content-Type.
+    ///
+    /// Falls back to utf8, replacing invalid sequences with U+FFFD, if the
+    /// body cannot be decoded; see `body_text`.
+    pub fn body_string(&self) -> String {
+        decode_body_lossy(&self.headers, &self.body)
+    }
+
+    /// The body of the response as a String, decoded using the charset of
+    /// its Content-Type.
+    ///
+    /// As with `extract_body_to_text`, invalid utf8 is decoded lossily rather
+    /// than returned as an error.
+    pub fn body_text(&self) -> Result<String, DecodeError> {
+        decode_body(&self.headers, &self.body)
+    }
+
+    /// The body of the response, deserialized from JSON into the given type.
+    #[cfg(feature = "json")]
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, JsonError> {
+        json::from_body(&self.body)
+    }
+
+    /// The body of the response as a generic JSON value.
+    #[cfg(feature = "json")]
+    pub fn json_value(&self) -> Result<Value, JsonError> {
+        self.json()
+    }
+
+    /// Assert that the JSON body of the response matches the given matcher.
+    #[cfg(feature = "json")]
+    pub fn assert_json(&self, matcher: &JsonMatcher) -> &TestResponse {
+        match self.json_value() {
+            Ok(actual) => {
+                if let Some(report) = matcher.report(&actual) {
+                    panic!("{}\n\n{}", report, self);
+                }
+            },
+            Err(err) => panic!("{}\n\n{}", err, self),
+        }
+        self
+    }
+
+    /// Assert that the JSON body of the response is equal to the expected document.
+    #[cfg(feature = "json")]
+    pub fn assert_json_eq(&self, expected: Value) -> &TestResponse {
+        self.assert_json(&JsonMatcher::new(expected))
+    }
+
+    /// Assert that the JSON body of the response contains the expected document.
+    #[cfg(feature = "json")]
+    pub fn assert_json_includes(&self, expected: Value) -> &TestResponse {
+        self.assert_json(&JsonMatcher::new(expected).subset())
+    }
+
+    /// Assert that the response has the given status.
+    pub fn assert_status(&self, expected: status::Status) -> &TestResponse {
+        if self.status != Some(expected) {
+            panic!("expected status {}, got {}\n\n{}", expected, StatusDisplay(self.status), self);
+        }
+        self
     }
 
-    result
+    /// Assert that the response has the given header.
+    pub fn assert_header<H>(&self, expected: H) -> &TestResponse
+    where H: Header + HeaderFormat + PartialEq + fmt::Debug {
+        match self.headers.get::<H>() {
+            Some(actual) if *actual == expected => (),
+            Some(actual) => panic!("expected header {:?}, got {:?}\n\n{}", expected, actual, self),
+            None => panic!("expected header {:?}, but {} is not set\n\n{}", expected, H::header_name(), self),
+        }
+        self
+    }
+
+    /// Assert that the body of the response is the given string.
+    pub fn assert_body(&self, expected: &str) -> &TestResponse {
+        if self.body != expected.as_bytes() {
+            panic!("expected body {:?}\n\n{}", expected, self);
+        }
+        self
+    }
+
+    /// Assert that the body of the response contains the given string.
+    pub fn assert_body_contains(&self, expected: &str) -> &TestResponse {
+        if !self.body_string().contains(expected) {
+            panic!("expected body to contain {:?}\n\n{}", expected, self);
+        }
+        self
+    }
+}
+
+impl From<Response> for TestResponse {
+    fn from(response: Response) -> TestResponse {
+        TestResponse::new(response)
+    }
+}
+
+impl From<IronResult<Response>> for TestResponse {
+    fn from(result: IronResult<Response>) -> TestResponse {
+        TestResponse::from_result(result)
+    }
+}
+
+impl fmt::Display for TestResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(f, "HTTP/1.1 {}", StatusDisplay(self.status)));
+        try!(write!(f, "{}", self.headers));
+        try!(writeln!(f, ""));
+        try!(write!(f, "{}", String::from_utf8_lossy(&self.body)));
+        if let Some(ref error) = self.error {
+            try!(write!(f, "\n\nhandler error: {}", error));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for TestResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+// Decode a body using the charset of the Content-Type header, if it has one.
+fn decode_body(headers: &Headers, body: &[u8]) -> Result<String, DecodeError> {
+    let charset = headers.get::<headers::ContentType>()
+        .and_then(|content_type| content_type.get_param(Attr::Charset))
+        .map(|charset| charset.to_string());
+
+    let encoding = match charset {
+        Some(ref charset) => match encoding_from_whatwg_label(charset) {
+            Some(encoding) => encoding,
+            None => return Err(DecodeError::UnsupportedCharset(charset.clone())),
+        },
+        None => UTF_8,
+    };
+
+    if encoding.name() == UTF_8.name() {
+        return Ok(match String::from_utf8(body.to_vec()) {
+            Ok(text) => text,
+            Err(err) => {
+                warn!("Response body is not valid utf8; decoding lossily; {}", err);
+                String::from_utf8_lossy(body).into_owned()
+            },
+        });
+    }
+
+    encoding.decode(body, DecoderTrap::Strict).map_err(|reason| {
+        DecodeError::Malformed { charset: encoding.name().to_owned(), reason: reason.into_owned() }
+    })
+}
+
+// Decode a body using the charset of the Content-Type header, falling back
+// to lossy utf8 if it cannot be decoded.
+fn decode_body_lossy(headers: &Headers, body: &[u8]) -> String {
+    decode_body(headers, body).unwrap_or_else(|err| {
+        warn!("{}; decoding the response body as utf8", err);
+        String::from_utf8_lossy(body).into_owned()
+    })
+}
+
+// Displays an optional status, as a Response may not have one.
+struct StatusDisplay(Option<status::Status>);
+
+impl fmt::Display for StatusDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(status) => write!(f, "{}", status),
+            None => write!(f, "<no status>"),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -23,6 +403,14 @@ mod test {
     use iron::prelude::*;
     use iron::{Handler, status};
 
+    use iron::headers::ContentLength;
+
+    #[cfg(feature = "compression")]
+    use iron::headers::{ContentEncoding, Encoding};
+    use iron::response::WriteBody;
+
+    use std::io::{self, Write};
+
     use request;
 
     use super::*;
@@ -54,4 +442,299 @@ mod test {
 
         assert_eq!(result, b"Hello, world!");
     }
+
+    #[test]
+    fn test_test_response() {
+        let response = request::get("http://localhost:3000",
+                           Headers::new(),
+                           &HelloWorldHandler);
+        let response = TestResponse::from_result(response);
+
+        assert_eq!(response.status(), Some(status::Ok));
+        assert_eq!(response.body_bytes(), b"Hello, world!");
+        assert_eq!(response.body_string(), "Hello, world!");
+        response.assert_status(status::Ok)
+            .assert_header(ContentLength(13))
+            .assert_body("Hello, world!")
+            .assert_body_contains("world");
+    }
+
+    #[test]
+    #[should_panic(expected = "expected status 404 Not Found, got 200 OK")]
+    fn test_test_response_assert_status() {
+        let response = request::get("http://localhost:3000",
+                           Headers::new(),
+                           &HelloWorldHandler);
+
+        TestResponse::from_result(response).assert_status(status::NotFound);
+    }
+
+    #[test]
+    #[should_panic(expected = "Hello, world!")]
+    fn test_test_response_assert_prints_response() {
+        let response = request::get("http://localhost:3000",
+                           Headers::new(),
+                           &HelloWorldHandler);
+
+        TestResponse::from_result(response).assert_body_contains("Goodbye");
+    }
+
+    #[derive(Debug)]
+    struct NotFoundError(u32);
+
+    impl fmt::Display for NotFoundError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "user {} not found", self.0)
+        }
+    }
+
+    impl Error for NotFoundError {
+        fn description(&self) -> &str {
+            "not found"
+        }
+    }
+
+    struct NotFoundHandler;
+
+    impl Handler for NotFoundHandler {
+        fn handle(&self, _: &mut Request) -> IronResult<Response> {
+            Err(IronError::new(NotFoundError(7), (status::NotFound, "No such user")))
+        }
+    }
+
+    #[test]
+    fn test_test_response_from_error() {
+        let response = request::get("http://localhost:3000", Headers::new(), &NotFoundHandler);
+        let response = TestResponse::from(response);
+
+        assert!(response.is_error());
+        assert_eq!(response.status(), Some(status::NotFound));
+        assert_eq!(response.body_string(), "No such user");
+        assert_eq!(response.error().unwrap().to_string(), "user 7 not found");
+        assert_eq!(response.error_as::<NotFoundError>().unwrap().0, 7);
+        assert!(response.error_as::<io::Error>().is_none());
+        assert!(response.to_string().ends_with("No such user\n\nhandler error: user 7 not found"));
+
+        let response = request::get("http://localhost:3000", Headers::new(), &HelloWorldHandler);
+        let response = TestResponse::from(response);
+        assert!(!response.is_error());
+        assert!(response.error().is_none());
+    }
+
+    #[cfg(feature = "json")]
+    struct JsonHandler;
+
+    #[cfg(feature = "json")]
+    impl Handler for JsonHandler {
+        fn handle(&self, _: &mut Request) -> IronResult<Response> {
+            Ok(Response::with((status::Ok, r#"{"id": 1, "name": "Example User"}"#)))
+        }
+    }
+
+    #[cfg(feature = "json")]
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct User {
+        id: u32,
+        name: String,
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_extract_body_to_json() {
+        let response = request::get("http://localhost:3000", Headers::new(), &JsonHandler);
+        let user: User = extract_body_to_json(response.unwrap()).unwrap();
+
+        assert_eq!(user, User { id: 1, name: "Example User".to_owned() });
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_test_response_json() {
+        let response = request::get("http://localhost:3000", Headers::new(), &JsonHandler);
+        let response = TestResponse::from_result(response);
+
+        assert_eq!(response.json_value().unwrap(), json!({"id": 1, "name": "Example User"}));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json_error_shows_body() {
+        let response = request::get("http://localhost:3000", Headers::new(), &HelloWorldHandler);
+        let err = TestResponse::from_result(response).json::<User>().unwrap_err();
+
+        assert!(err.to_string().ends_with("\n\nbody:\nHello, world!"));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_assert_json() {
+        let response = request::get("http://localhost:3000", Headers::new(), &JsonHandler);
+        let response = TestResponse::from_result(response);
+
+        response.assert_json_eq(json!({"id": 1, "name": "Example User"}))
+            .assert_json_includes(json!({"name": "Example User"}))
+            .assert_json(&JsonMatcher::new(json!({"id": 0, "name": "Example User"})).ignore("$.id"));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    #[should_panic(expected = "$.name: expected \"Other User\", got \"Example User\"")]
+    fn test_assert_json_includes() {
+        let response = request::get("http://localhost:3000", Headers::new(), &JsonHandler);
+
+        assert_json_includes(response.unwrap(), json!({"name": "Other User"}));
+    }
+
+    struct CharsetHandler(&'static str, &'static [u8]);
+
+    impl Handler for CharsetHandler {
+        fn handle(&self, _: &mut Request) -> IronResult<Response> {
+            let mut response = Response::with((status::Ok, self.1));
+            response.headers.set_raw("Content-Type", vec![self.0.as_bytes().to_vec()]);
+            Ok(response)
+        }
+    }
+
+    fn get(handler: &CharsetHandler) -> Response {
+        request::get("http://localhost:3000", Headers::new(), handler).unwrap()
+    }
+
+    #[test]
+    fn test_extract_body_to_text_charset() {
+        let latin1 = CharsetHandler("text/plain; charset=ISO-8859-1", b"caf\xe9");
+        assert_eq!(extract_body_to_text(get(&latin1)).unwrap(), "caf\u{e9}");
+
+        let shift_jis = CharsetHandler("text/plain; charset=Shift_JIS", b"\x93\xfa\x96\x7b");
+        assert_eq!(extract_body_to_string(get(&shift_jis)), "\u{65e5}\u{672c}");
+
+        let utf8 = CharsetHandler("text/plain; charset=utf-8", "caf\u{e9}".as_bytes());
+        assert_eq!(extract_body_to_text(get(&utf8)).unwrap(), "caf\u{e9}");
+    }
+
+    #[test]
+    fn test_extract_body_to_text_lossy_utf8() {
+        let handler = CharsetHandler("text/plain", b"caf\xe9");
+
+        assert_eq!(extract_body_to_text(get(&handler)).unwrap(), "caf\u{fffd}");
+    }
+
+    #[test]
+    fn test_extract_body_to_text_errors() {
+        let unknown = CharsetHandler("text/plain; charset=x-unknown", b"body");
+        assert_eq!(extract_body_to_text(get(&unknown)).unwrap_err(),
+                   DecodeError::UnsupportedCharset("x-unknown".to_owned()));
+
+        let malformed = CharsetHandler("text/plain; charset=Shift_JIS", b"\x93");
+        match extract_body_to_text(get(&malformed)).unwrap_err() {
+            DecodeError::Malformed { ref charset, .. } => assert_eq!(charset, "windows-31j"),
+            err => panic!("expected a malformed body error, got {:?}", err),
+        }
+
+        let response = TestResponse::new(get(&unknown));
+        assert_eq!(response.body_string(), "body");
+    }
+
+    #[test]
+    fn test_extract_body_to_string_fallback() {
+        let unknown = CharsetHandler("text/plain; charset=x-unknown", b"plain ascii");
+        assert_eq!(extract_body_to_string(get(&unknown)), "plain ascii");
+
+        let malformed = CharsetHandler("text/plain; charset=Shift_JIS", b"caf\x93");
+        assert_eq!(extract_body_to_string(get(&malformed)), "caf\u{fffd}");
+    }
+
+    struct FailingBody;
+
+    impl WriteBody for FailingBody {
+        fn write_body(&mut self, res: &mut Write) -> io::Result<()> {
+            try!(res.write_all(b"partial"));
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "connection lost"))
+        }
+    }
+
+    struct FailingBodyHandler;
+
+    impl Handler for FailingBodyHandler {
+        fn handle(&self, _: &mut Request) -> IronResult<Response> {
+            let mut response = Response::with(status::Ok);
+            response.body = Some(Box::new(FailingBody));
+            Ok(response)
+        }
+    }
+
+    #[test]
+    fn test_try_extract_body_to_bytes() {
+        let response = request::get("http://localhost:3000", Headers::new(), &HelloWorldHandler);
+        assert_eq!(try_extract_body_to_bytes(response.unwrap()).unwrap(), b"Hello, world!");
+
+        let response = request::get("http://localhost:3000", Headers::new(), &FailingBodyHandler);
+        let err = try_extract_body_to_bytes(response.unwrap()).unwrap_err();
+        assert_eq!(err.bytes, b"partial");
+        assert_eq!(err.error.kind(), io::ErrorKind::BrokenPipe);
+        assert_eq!(err.to_string(), "failed to write response body after 7 bytes: connection lost");
+
+        let response = request::get("http://localhost:3000", Headers::new(), &FailingBodyHandler);
+        assert!(TestResponse::try_new(response.unwrap()).is_err());
+    }
+
+    #[cfg(feature = "compression")]
+    struct CompressedHandler(Vec<Encoding>, Vec<u8>);
+
+    #[cfg(feature = "compression")]
+    impl Handler for CompressedHandler {
+        fn handle(&self, _: &mut Request) -> IronResult<Response> {
+            let mut response = Response::with((status::Ok, self.1.clone()));
+            response.headers.set(ContentEncoding(self.0.clone()));
+            Ok(response)
+        }
+    }
+
+    #[cfg(feature = "compression")]
+    fn compressed(encodings: Vec<Encoding>, body: Vec<u8>) -> TestResponse {
+        let handler = CompressedHandler(encodings, body);
+        TestResponse::from_result(request::get("http://localhost:3000", Headers::new(), &handler))
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_decoded_body() {
+        use brotli::CompressorWriter;
+        use flate2::Compression;
+        use flate2::write::{GzEncoder, ZlibEncoder};
+
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(b"Hello, world!").unwrap();
+        let gzip = gzip.finish().unwrap();
+        let response = compressed(vec![Encoding::Gzip], gzip.clone());
+        assert_eq!(response.body_bytes(), &gzip[..]);
+        assert_eq!(response.decoded_body_string().unwrap(), "Hello, world!");
+
+        let mut deflate = ZlibEncoder::new(Vec::new(), Compression::default());
+        deflate.write_all(b"Hello, world!").unwrap();
+        let response = compressed(vec![Encoding::Deflate], deflate.finish().unwrap());
+        assert_eq!(response.decoded_body_bytes().unwrap(), b"Hello, world!");
+
+        let mut brotli = Vec::new();
+        {
+            let mut writer = CompressorWriter::new(&mut brotli, 4096, 5, 22);
+            writer.write_all(b"Hello, world!").unwrap();
+        }
+        let response = compressed(vec![Encoding::EncodingExt("br".to_owned())], brotli);
+        assert_eq!(response.decoded_body_bytes().unwrap(), b"Hello, world!");
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_decoded_body_errors() {
+        let response = compressed(vec![Encoding::Gzip], b"Hello, world!".to_vec());
+        assert!(response.decoded_body_bytes().is_err());
+
+        let response = compressed(vec![Encoding::Compress], b"Hello, world!".to_vec());
+        assert_eq!(response.decoded_body_bytes().unwrap_err().to_string(),
+                   "unsupported Content-Encoding `compress`");
+
+        let handler = CompressedHandler(vec![Encoding::Identity], b"Hello, world!".to_vec());
+        let response = request::get("http://localhost:3000", Headers::new(), &handler);
+        assert_eq!(extract_body_to_decoded_bytes(response.unwrap()).unwrap(), b"Hello, world!");
+    }
 }
@@ -0,0 +1,478 @@
+use hyper;
+use hyper::net::{HttpListener, NetworkListener, NetworkStream};
+
+use iron::headers::{ContentLength, Host};
+use iron::prelude::*;
+use iron::{Handler, Headers, Listening, Protocol, method};
+
+use std::io::{self, Read, Write};
+use std::mem;
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::time::Duration;
+
+use super::wire::WireResponse;
+
+/// An Iron server listening on an ephemeral port of the loopback interface.
+///
+/// Unlike the functions in `request`, requests to a `TestServer` go through
+/// Iron's real listener, thread pool, keep-alive handling and timeouts.
+///
+/// When the `TestServer` is dropped, it closes the connections it accepted,
+/// stops its threads and waits for them to let go of the Handler and the
+/// listening socket. hyper 0.10's threads can only be stopped by unwinding
+/// them, so if the crate is built with `panic = "abort"` they are parked
+/// instead, and the socket stays open until the process exits.
+pub struct TestServer {
+    addr: SocketAddr,
+    shared: Arc<Shared>,
+    threads: usize,
+    listening: Option<Listening>,
+}
+
+impl TestServer {
+    /// Start a server for the given Handler on `127.0.0.1:0`, with four
+    /// request handling threads.
+    ///
+    /// Panics if the server cannot be started.
+    pub fn new<H: Handler>(handler: H) -> TestServer {
+        let mut iron = Iron::new(handler);
+        iron.threads = 4;
+        match TestServer::start(iron) {
+            Ok(server) => server,
+            Err(err) => panic!("failed to start test server: {}", err),
+        }
+    }
+
+    /// Start a server for the given Iron on `127.0.0.1:0`, keeping its
+    /// timeouts and number of threads.
+    pub fn start<H: Handler>(iron: Iron<H>) -> io::Result<TestServer> {
+        let mut listener = try!(HttpListener::new("127.0.0.1:0").map_err(to_io_error));
+        let addr = try!(listener.local_addr());
+        let shared = Arc::new(Shared::default());
+        let threads = iron.threads;
+
+        let handler = TrackedHandler { handler: iron.handler, _live: Live::new(&shared) };
+        let mut tracked = Iron::new(handler);
+        tracked.timeouts = iron.timeouts;
+        tracked.threads = iron.threads;
+        let listener = ShutdownListener::new(listener, shared.clone());
+        let listening = try!(tracked.listen(listener, Protocol::http()).map_err(to_io_error));
+
+        Ok(TestServer {
+            addr: addr,
+            shared: shared,
+            threads: threads,
+            listening: Some(listening),
+        })
+    }
+
+    /// The address the server is listening on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The URL of the given path on the server.
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    /// Open a connection to the server, to send requests by hand.
+    pub fn connect(&self) -> io::Result<TcpStream> {
+        let stream = try!(TcpStream::connect(self.addr));
+        try!(stream.set_read_timeout(Some(Duration::from_secs(30))));
+        Ok(stream)
+    }
+
+    /// Send the given bytes to the server on a new connection, and read the
+    /// response until the server closes the connection.
+    pub fn send_raw(&self, bytes: &[u8]) -> io::Result<WireResponse> {
+        let mut stream = try!(self.connect());
+        try!(stream.write_all(bytes));
+        // Let the server see the end of the request, so that it closes the
+        // connection instead of waiting for another request.
+        try!(stream.shutdown(Shutdown::Write));
+
+        let mut response = Vec::new();
+        try!(stream.read_to_end(&mut response));
+        WireResponse::from_bytes(response).map_err(to_io_error)
+    }
+
+    /// Send a request with the given method, path, headers and body to the
+    /// server. The Host and Content-Length headers are set for you.
+    pub fn request(&self, method: method::Method, path: &str, headers: Headers, body: &str) -> io::Result<WireResponse> {
+        let mut headers = headers;
+        if !headers.has::<Host>() {
+            headers.set(Host { hostname: self.addr.ip().to_string(), port: Some(self.addr.port()) });
+        }
+        headers.set(ContentLength(body.len() as u64));
+
+        let request = format!("{} {} HTTP/1.1\r\n{}\r\n{}", method, path, headers, body);
+        self.send_raw(request.as_bytes())
+    }
+
+    /// Send a GET request for the given path to the server.
+    pub fn get(&self, path: &str) -> io::Result<WireResponse> {
+        self.request(method::Get, path, Headers::new(), "")
+    }
+
+    /// Send a POST request with a body to the given path on the server.
+    pub fn post(&self, path: &str, body: &str) -> io::Result<WireResponse> {
+        self.request(method::Post, path, Headers::new(), body)
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        {
+            let mut state = self.shared.state.lock().unwrap();
+            state.shutdown = true;
+            // Unblock the threads serving open connections, including those
+            // waiting for another request on a kept-alive connection.
+            for stream in state.connections.drain(..).filter_map(|stream| stream.upgrade()) {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+        }
+        // Wake each thread waiting for a connection, so that it sees the
+        // server has been shut down.
+        for _ in 0..self.threads {
+            let _ = TcpStream::connect(self.addr);
+        }
+
+        if cfg!(panic = "unwind") {
+            // Wait for the supervisor thread, and then for every other thread
+            // to drop its listener and the Handler.
+            self.listening.take();
+            let mut state = self.shared.state.lock().unwrap();
+            while state.live > 0 {
+                state = self.shared.stopped.wait(state).unwrap();
+            }
+        } else {
+            // The threads are parked rather than stopped, so joining the
+            // supervisor would never return.
+            mem::forget(self.listening.take());
+        }
+    }
+}
+
+// The state shared between a server and its listeners.
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    stopped: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    shutdown: bool,
+    // The connections accepted so far, to be closed on shutdown. A connection
+    // is closed as soon as hyper drops it, whether or not it was shut down.
+    connections: Vec<Weak<TcpStream>>,
+    // The number of listeners and Handlers still held by the server's threads.
+    live: usize,
+}
+
+// Counts as live until it is dropped. Declared as the last field of its
+// owner, so that the owner's other fields are dropped first.
+struct Live(Arc<Shared>);
+
+impl Live {
+    fn new(shared: &Arc<Shared>) -> Live {
+        shared.state.lock().unwrap().live += 1;
+        Live(shared.clone())
+    }
+}
+
+impl Drop for Live {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().live -= 1;
+        self.0.stopped.notify_all();
+    }
+}
+
+// A Handler which lets the server know when hyper's threads have dropped it.
+struct TrackedHandler<H> {
+    handler: H,
+    _live: Live,
+}
+
+impl<H: Handler> Handler for TrackedHandler<H> {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        self.handler.handle(req)
+    }
+}
+
+// A listener which records the connections it accepts, and stops the threads
+// of the server once it is shut down.
+//
+// hyper 0.10 cannot close a server: each of its threads accepts connections
+// forever, and a supervisor thread replaces any which panic, cloning the
+// listener for it. Once the server is shut down, accepting a connection
+// unwinds the thread which accepted it, and cloning the listener unwinds the
+// supervisor. `resume_unwind` does not call the panic hook, so nothing is
+// printed.
+struct ShutdownListener {
+    listener: HttpListener,
+    shared: Arc<Shared>,
+    _live: Live,
+}
+
+impl ShutdownListener {
+    fn new(listener: HttpListener, shared: Arc<Shared>) -> ShutdownListener {
+        let live = Live::new(&shared);
+        ShutdownListener { listener: listener, shared: shared, _live: live }
+    }
+
+    fn check_shutdown(&self) {
+        if self.shared.state.lock().unwrap().shutdown {
+            stop_thread();
+        }
+    }
+}
+
+#[cfg(panic = "unwind")]
+fn stop_thread() -> ! {
+    ::std::panic::resume_unwind(Box::new("test server shut down"))
+}
+
+#[cfg(not(panic = "unwind"))]
+fn stop_thread() -> ! {
+    loop {
+        ::std::thread::park();
+    }
+}
+
+impl Clone for ShutdownListener {
+    fn clone(&self) -> ShutdownListener {
+        self.check_shutdown();
+        ShutdownListener::new(self.listener.clone(), self.shared.clone())
+    }
+}
+
+impl NetworkListener for ShutdownListener {
+    type Stream = SharedStream;
+
+    fn accept(&mut self) -> hyper::Result<SharedStream> {
+        self.check_shutdown();
+        let stream = Arc::new(try!(self.listener.accept()).0);
+
+        let mut state = self.shared.state.lock().unwrap();
+        if state.shutdown {
+            drop(state);
+            stop_thread();
+        }
+        state.connections.retain(|stream| stream.upgrade().is_some());
+        state.connections.push(Arc::downgrade(&stream));
+        Ok(SharedStream(stream))
+    }
+
+    fn local_addr(&mut self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.listener.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) {
+        self.listener.set_write_timeout(timeout)
+    }
+}
+
+// A connection accepted by the server. Unlike an HttpStream, clones share the
+// same socket rather than duplicating it, so that the server can keep track
+// of the connection without keeping it open.
+#[derive(Clone)]
+struct SharedStream(Arc<TcpStream>);
+
+impl Read for SharedStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&*self.0).read(buf)
+    }
+}
+
+impl Write for SharedStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&*self.0).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&*self.0).flush()
+    }
+}
+
+impl NetworkStream for SharedStream {
+    fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+        self.0.peer_addr()
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.0.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.0.set_write_timeout(timeout)
+    }
+
+    fn close(&mut self, how: Shutdown) -> io::Result<()> {
+        match self.0.shutdown(how) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotConnected => Ok(()),
+            result => result,
+        }
+    }
+}
+
+fn to_io_error(err: hyper::Error) -> io::Error {
+    match err {
+        hyper::Error::Io(err) => err,
+        err => io::Error::new(io::ErrorKind::Other, err),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use iron::headers::{Connection, UserAgent};
+    use iron::prelude::*;
+    use iron::{Handler, Headers, status};
+
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+
+    struct EchoHandler;
+
+    impl Handler for EchoHandler {
+        fn handle(&self, req: &mut Request) -> IronResult<Response> {
+            let mut body = String::new();
+            req.body.read_to_string(&mut body).unwrap();
+            let agent = req.headers.get::<UserAgent>().map(|agent| agent.to_string()).unwrap_or_default();
+            Ok(Response::with((status::Ok, format!("{} {} {} {}", req.method, req.url, agent, body))))
+        }
+    }
+
+    #[test]
+    fn test_requests() {
+        let server = TestServer::new(EchoHandler);
+        assert_eq!(server.addr().ip().to_string(), "127.0.0.1");
+        assert!(server.addr().port() != 0);
+
+        let response = server.get("/users?page=2").unwrap();
+        assert_eq!(response.status(), status::Ok);
+        assert_eq!(response.body(), format!("GET {}  ", server.url("/users?page=2")).as_bytes());
+
+        let mut headers = Headers::new();
+        headers.set(UserAgent("iron-test".to_owned()));
+        let response = server.request(method::Put, "/users/1", headers, "name=Example").unwrap();
+        assert_eq!(response.body(), format!("PUT {} iron-test name=Example", server.url("/users/1")).as_bytes());
+    }
+
+    #[test]
+    fn test_send_raw() {
+        let server = TestServer::new(EchoHandler);
+
+        // Iron rejects a request without a Host header.
+        let response = server.send_raw(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(response.status(), status::BadRequest);
+    }
+
+    #[test]
+    fn test_keep_alive() {
+        let server = TestServer::new(|_: &mut Request| Ok(Response::with((status::Ok, "Hello"))));
+        let mut stream = server.connect().unwrap();
+        let request = format!("GET / HTTP/1.1\r\nHost: {}\r\n\r\n", server.addr());
+
+        // Both requests are answered on the same connection.
+        for _ in 0..2 {
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut response = Vec::new();
+            while !response.ends_with(b"\r\n\r\nHello") {
+                let mut buf = [0; 1024];
+                let n = stream.read(&mut buf).unwrap();
+                assert!(n > 0, "connection closed");
+                response.extend(&buf[..n]);
+            }
+            assert!(response.starts_with(b"HTTP/1.1 200 OK\r\n"));
+        }
+    }
+
+    #[test]
+    fn test_timeouts() {
+        let mut iron = Iron::new(EchoHandler);
+        iron.threads = 1;
+        iron.timeouts.read = Some(Duration::from_millis(50));
+        let server = TestServer::start(iron).unwrap();
+
+        // The server closes a connection which does not finish its request.
+        let mut stream = server.connect().unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        assert!(response.is_empty());
+
+        let mut headers = Headers::new();
+        headers.set(Connection::close());
+        let response = server.request(method::Get, "/", headers, "").unwrap();
+        assert_eq!(response.status(), status::Ok);
+    }
+
+    #[test]
+    fn test_shutdown_on_drop() {
+        let server = TestServer::new(EchoHandler);
+        let addr = server.addr();
+        drop(server);
+
+        assert!(TcpStream::connect(addr).is_err());
+    }
+
+    #[test]
+    fn test_shutdown_with_open_connection() {
+        // Each thread holds the Handler, and with it the token.
+        let token = Arc::new(());
+        let handler_token = token.clone();
+        let server = TestServer::new(move |_: &mut Request| {
+            Ok(Response::with((status::Ok, Arc::strong_count(&handler_token).to_string())))
+        });
+        let addr = server.addr();
+
+        // A connection which is idle between requests, and one which has only
+        // sent part of a request.
+        let mut idle = server.connect().unwrap();
+        idle.write_all(format!("GET / HTTP/1.1\r\nHost: {}\r\n\r\n", addr).as_bytes()).unwrap();
+        let mut response = Vec::new();
+        while !response.ends_with(b"\r\n\r\n2") {
+            let mut buf = [0; 1024];
+            let n = idle.read(&mut buf).unwrap();
+            assert!(n > 0, "connection closed");
+            response.extend(&buf[..n]);
+        }
+        let mut partial = server.connect().unwrap();
+        partial.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+
+        drop(server);
+
+        assert_eq!(Arc::strong_count(&token), 1);
+        assert!(TcpStream::connect(addr).is_err());
+        for stream in &mut [idle, partial] {
+            let mut rest = Vec::new();
+            assert!(stream.read_to_end(&mut rest).map(|n| n == 0).unwrap_or(true));
+        }
+    }
+
+    #[test]
+    fn test_handler_panic() {
+        let mut iron = Iron::new(|req: &mut Request| {
+            if req.url.path() == vec!["panic"] {
+                panic!("handler failed");
+            }
+            Ok(Response::with((status::Ok, "Hello")))
+        });
+        iron.threads = 1;
+        let server = TestServer::start(iron).unwrap();
+
+        assert_eq!(server.get("/panic").unwrap().status(), status::InternalServerError);
+        assert_eq!(server.get("/").unwrap().body(), b"Hello");
+    }
+}
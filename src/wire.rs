@@ -0,0 +1,251 @@
+use hyper;
+use hyper::buffer::BufReader;
+use hyper::http::RawStatus;
+use hyper::http::h1::{self, HttpReader, Incoming};
+use hyper::server::Response as HttpResponse;
+use hyper::version::HttpVersion;
+
+use iron::headers::{ContentLength, Encoding, TransferEncoding};
+use iron::prelude::*;
+use iron::{status, Headers};
+
+use std::fmt;
+use std::io::{Cursor, Read};
+
+use super::mock_stream::MockStream;
+
+/// A response as Iron writes it to the network.
+///
+/// The Response is written to a `MockStream` with Iron's own `write_back`, so
+/// the bytes include the headers Iron and hyper add, such as Content-Type,
+/// Content-Length, Date and Transfer-Encoding. They are then parsed back into
+/// their status line, headers and body.
+pub struct WireResponse {
+    bytes: Vec<u8>,
+    version: HttpVersion,
+    status: RawStatus,
+    headers: Headers,
+    body: Vec<u8>,
+    complete: bool,
+}
+
+impl WireResponse {
+    /// Write the given Response as Iron would, and parse it back.
+    ///
+    /// Panics if the written response cannot be parsed.
+    pub fn new(response: Response) -> WireResponse {
+        let mut stream = MockStream::duplex(vec![]);
+        {
+            let mut headers = Headers::new();
+            response.write_back(HttpResponse::new(&mut stream, &mut headers));
+        }
+
+        match WireResponse::from_bytes(stream.written()) {
+            Ok(response) => response,
+            Err(err) => panic!("failed to parse the response written by Iron: {}", err),
+        }
+    }
+
+    /// Write the result of a request as Iron would, and parse it back. If the
+    /// Handler returned an error, its response is written.
+    pub fn from_result(result: IronResult<Response>) -> WireResponse {
+        match result {
+            Ok(response) => WireResponse::new(response),
+            Err(err) => WireResponse::new(err.response),
+        }
+    }
+
+    /// Parse a response from the given bytes.
+    ///
+    /// The body is read according to the Transfer-Encoding or Content-Length
+    /// of the response; see `is_complete`.
+    pub fn from_bytes(bytes: Vec<u8>) -> hyper::Result<WireResponse> {
+        let (version, status, headers, body, complete) = {
+            let mut reader = BufReader::new(Cursor::new(&bytes[..]));
+            let Incoming { version, subject: status, headers } = try!(h1::parse_response(&mut reader));
+
+            let chunked = headers.get::<TransferEncoding>()
+                .map_or(false, |encodings| encodings.last() == Some(&Encoding::Chunked));
+            let mut body_reader = match status.0 {
+                100...199 | 204 | 304 => HttpReader::EmptyReader(&mut reader),
+                _ if chunked => HttpReader::ChunkedReader(&mut reader, None),
+                _ => match headers.get::<ContentLength>() {
+                    Some(&ContentLength(len)) => HttpReader::SizedReader(&mut reader, len),
+                    None => HttpReader::EofReader(&mut reader),
+                },
+            };
+
+            // Keep what can be read of a truncated body.
+            let mut body = Vec::new();
+            let complete = body_reader.read_to_end(&mut body).is_ok();
+            (version, status, headers, body, complete)
+        };
+
+        Ok(WireResponse {
+            bytes: bytes,
+            version: version,
+            status: status,
+            headers: headers,
+            body: body,
+            complete: complete,
+        })
+    }
+
+    /// The bytes of the response, as they were written.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The HTTP version of the status line.
+    pub fn version(&self) -> HttpVersion {
+        self.version
+    }
+
+    /// The status code of the status line.
+    pub fn status(&self) -> status::Status {
+        status::Status::from_u16(self.status.0)
+    }
+
+    /// The reason phrase of the status line.
+    pub fn reason(&self) -> &str {
+        &self.status.1
+    }
+
+    /// The headers of the response.
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// The body of the response, with any chunked encoding removed.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Whether the whole body was written, according to the Transfer-Encoding
+    /// or Content-Length of the response. The body is incomplete if writing
+    /// it failed part of the way through.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+}
+
+impl fmt::Debug for WireResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.bytes))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use iron::headers::{ContentLength, ContentType, Date, TransferEncoding};
+    use iron::modifiers::Header;
+    use iron::prelude::*;
+    use iron::response::WriteBody;
+    use iron::{Handler, status};
+
+    use std::io::{self, Write};
+
+    use request;
+
+    use super::*;
+
+    struct StreamingBody;
+
+    impl WriteBody for StreamingBody {
+        fn write_body(&mut self, res: &mut Write) -> io::Result<()> {
+            try!(res.write_all(b"Hello, "));
+            res.write_all(b"world!")
+        }
+    }
+
+    struct FailingBody;
+
+    impl WriteBody for FailingBody {
+        fn write_body(&mut self, res: &mut Write) -> io::Result<()> {
+            try!(res.write_all(b"Hello"));
+            Err(io::Error::new(io::ErrorKind::Other, "disk on fire"))
+        }
+    }
+
+    struct ErrorHandler;
+
+    impl Handler for ErrorHandler {
+        fn handle(&self, _: &mut Request) -> IronResult<Response> {
+            Err(IronError::new(io::Error::new(io::ErrorKind::Other, "failed"),
+                               (status::BadGateway, "Bad gateway")))
+        }
+    }
+
+    #[test]
+    fn test_sized_body() {
+        let response = WireResponse::new(Response::with((status::Ok, "Hello, world!")));
+
+        assert_eq!(response.version(), HttpVersion::Http11);
+        assert_eq!(response.status(), status::Ok);
+        assert_eq!(response.reason(), "OK");
+        assert_eq!(response.headers().get::<ContentLength>(), Some(&ContentLength(13)));
+        assert_eq!(response.headers().get::<ContentType>().unwrap().to_string(), "text/plain");
+        assert!(response.headers().has::<Date>());
+        assert_eq!(response.body(), b"Hello, world!");
+        assert!(response.is_complete());
+        assert!(response.bytes().starts_with(b"HTTP/1.1 200 OK\r\n"));
+        assert!(response.bytes().ends_with(b"\r\n\r\nHello, world!"));
+    }
+
+    #[test]
+    fn test_chunked_body() {
+        let body: Box<WriteBody> = Box::new(StreamingBody);
+        let response = WireResponse::new(Response::with((status::Ok, body)));
+
+        assert!(response.headers().has::<TransferEncoding>());
+        assert!(!response.headers().has::<ContentLength>());
+        assert_eq!(response.body(), b"Hello, world!");
+        assert!(response.bytes().ends_with(b"\r\n\r\n7\r\nHello, \r\n6\r\nworld!\r\n0\r\n\r\n"));
+    }
+
+    #[test]
+    fn test_empty_bodies() {
+        let response = WireResponse::new(Response::with(status::NoContent));
+        assert_eq!(response.headers().get::<ContentLength>(), Some(&ContentLength(0)));
+        assert_eq!(response.body(), b"");
+
+        let response = WireResponse::new(Response::new());
+        assert_eq!(response.status(), status::NotFound);
+
+        // Iron writes the body of a response to a HEAD request as well.
+        let response = request::head("http://localhost:3000", Headers::new(), &|_: &mut Request| {
+            Ok(Response::with((status::Ok, "Hello, world!")))
+        });
+        assert_eq!(WireResponse::from_result(response).body(), b"Hello, world!");
+    }
+
+    #[test]
+    fn test_incomplete_body() {
+        let body: Box<WriteBody> = Box::new(FailingBody);
+        let response = WireResponse::new(Response::with((status::Ok, body, Header(ContentLength(13)))));
+
+        assert_eq!(response.body(), b"Hello");
+        assert!(!response.is_complete());
+    }
+
+    #[test]
+    fn test_from_result() {
+        let response = request::get("http://localhost:3000", Headers::new(), &ErrorHandler);
+        let response = WireResponse::from_result(response);
+
+        assert_eq!(response.status(), status::BadGateway);
+        assert_eq!(response.body(), b"Bad gateway");
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let response = WireResponse::from_bytes(b"HTTP/1.0 418 Short And Stout\r\n\r\nteapot".to_vec()).unwrap();
+
+        assert_eq!(response.version(), HttpVersion::Http10);
+        assert_eq!(response.status(), status::ImATeapot);
+        assert_eq!(response.reason(), "Short And Stout");
+        assert_eq!(response.body(), b"teapot");
+
+        assert!(WireResponse::from_bytes(b"not a response".to_vec()).is_err());
+    }
+}